@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+pub const HELP_FLAG: &str = "--help";
+pub const SHORT_HELP_FLAG: &str = "-h";
+const END_OF_OPTIONS: &str = "--";
+
+/// An option, a flag when it takes no value.
+pub struct Opt {
+    pub name: &'static str,
+    pub value: Option<&'static str>,
+    pub help: &'static str,
+}
+
+pub enum Arity {
+    Required,
+    Optional,
+    /// One or more.
+    Many,
+}
+
+pub struct Positional {
+    pub name: &'static str,
+    pub arity: Arity,
+    pub help: &'static str,
+}
+
+/// What a command accepts, used both to parse its arguments and to describe them.
+pub struct Spec {
+    pub options: &'static [Opt],
+    pub positionals: &'static [Positional],
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<OutputFormat, ()> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+pub enum ArgError {
+    UnknownOption(String),
+    MissingValue(&'static str),
+    Repeated(&'static str),
+    MissingArgument(&'static str),
+    UnexpectedArgument(String),
+    InvalidValue { name: &'static str, value: String, expected: &'static str },
+}
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgError::UnknownOption(arg) => write!(f, "unknown option \"{}\"", arg),
+            ArgError::MissingValue(name) => write!(f, "{} needs a value", name),
+            ArgError::Repeated(name) => write!(f, "{} is given more than once", name),
+            ArgError::MissingArgument(name) => write!(f, "missing {}", name),
+            ArgError::UnexpectedArgument(arg) => write!(f, "unexpected argument \"{}\"", arg),
+            ArgError::InvalidValue { name, value, expected } => write!(f, "invalid {} \"{}\", expected {}", name, value, expected),
+        }
+    }
+}
+
+/// Parsed arguments. Positionals are taken in the declared order.
+pub struct Args {
+    options: HashMap<&'static str, Option<String>>,
+    positionals: std::vec::IntoIter<String>,
+    /// Output format asked for with the global option.
+    pub output: OutputFormat,
+}
+
+impl Args {
+    pub fn flag(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+
+    pub fn value(&mut self, name: &str) -> Option<String> {
+        self.options.remove(name).flatten()
+    }
+
+    /// The value of the option converted, the expectation is shown when it doesn't convert.
+    pub fn parse<T: FromStr>(&mut self, name: &'static str, expected: &'static str) -> Result<Option<T>, ArgError> {
+        match self.value(name) {
+            Some(value) => match value.parse() {
+                Ok(v) => Ok(Some(v)),
+                Err(_) => Err(ArgError::InvalidValue { name, value, expected }),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// The next positional, validated by the parser when it's required.
+    pub fn next(&mut self) -> Option<String> {
+        self.positionals.next()
+    }
+
+    /// Everything not taken yet.
+    pub fn rest(&mut self) -> Vec<String> {
+        self.positionals.by_ref().collect()
+    }
+}
+
+/// Parses the command arguments, options may be given anywhere and "--" ends them.
+pub fn parse(spec: &Spec, args: Vec<String>) -> Result<Args, ArgError> {
+    let (options, positionals, _) = parse_options(spec, args, false)?;
+
+    let mut min = 0;
+    let mut max = Some(0);
+    for positional in spec.positionals.iter() {
+        match positional.arity {
+            Arity::Required => { min += 1; max = max.map(|m| m + 1); },
+            Arity::Optional => max = max.map(|m| m + 1),
+            Arity::Many => { min += 1; max = None; },
+        }
+    }
+
+    if positionals.len() < min {
+        let missing = spec.positionals.iter()
+            .filter(|p| !matches!(p.arity, Arity::Optional))
+            .nth(positionals.len())
+            .map(|p| p.name)
+            .unwrap_or_default();
+        return Err(ArgError::MissingArgument(missing));
+    }
+
+    if let Some(max) = max {
+        if positionals.len() > max {
+            return Err(ArgError::UnexpectedArgument(positionals[max].clone()));
+        }
+    }
+
+    Ok(Args { options, positionals: positionals.into_iter(), output: OutputFormat::Text })
+}
+
+/// Parses the options in front of the command, returns them with the command and its arguments.
+pub fn parse_leading(spec: &Spec, args: Vec<String>) -> Result<(Args, Vec<String>), ArgError> {
+    let (options, _, rest) = parse_options(spec, args, true)?;
+    Ok((Args { options, positionals: vec![].into_iter(), output: OutputFormat::Text }, rest))
+}
+
+type Parsed = (HashMap<&'static str, Option<String>>, Vec<String>, Vec<String>);
+
+fn parse_options(spec: &Spec, args: Vec<String>, leading_only: bool) -> Result<Parsed, ArgError> {
+    let mut options = HashMap::new();
+    let mut positionals = vec![];
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        if arg == END_OF_OPTIONS {
+            if leading_only {
+                return Ok((options, positionals, args.collect()));
+            }
+            positionals.extend(args.by_ref());
+            break;
+        }
+
+        if !arg.starts_with('-') || arg.len() == 1 {
+            if leading_only {
+                return Ok((options, positionals, std::iter::once(arg).chain(args).collect()));
+            }
+            positionals.push(arg);
+            continue;
+        }
+
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(String::from(value))),
+            None => (arg.as_str(), None),
+        };
+
+        let opt = match spec.options.iter().find(|o| o.name == name) {
+            Some(o) => o,
+            None => return Err(ArgError::UnknownOption(arg)),
+        };
+
+        let value = match (opt.value, inline_value) {
+            (Some(_), Some(value)) => Some(value),
+            (Some(_), None) => Some(args.next().ok_or(ArgError::MissingValue(opt.name))?),
+            (None, Some(_)) => return Err(ArgError::UnexpectedArgument(arg)),
+            (None, None) => None,
+        };
+
+        if options.insert(opt.name, value).is_some() {
+            return Err(ArgError::Repeated(opt.name));
+        }
+    }
+
+    Ok((options, positionals, vec![]))
+}
+
+pub fn is_help(args: &[String]) -> bool {
+    args.iter()
+        .take_while(|a| *a != END_OF_OPTIONS)
+        .any(|a| a == HELP_FLAG || a == SHORT_HELP_FLAG)
+}
+
+/// One line summary of the arguments, like "[-c] <key>".
+pub fn usage(spec: &Spec) -> String {
+    let options = spec.options.iter().map(|o| match o.value {
+        Some(value) => format!("[{} {}]", o.name, value),
+        None => format!("[{}]", o.name),
+    });
+
+    let positionals = spec.positionals.iter().map(|p| match p.arity {
+        Arity::Required => String::from(p.name),
+        Arity::Optional => format!("[{}]", p.name),
+        Arity::Many => format!("{}...", p.name),
+    });
+
+    options.chain(positionals).collect::<Vec<String>>().join(" ")
+}
+
+/// Every argument with its description.
+pub fn describe(spec: &Spec) -> Vec<(String, &'static str)> {
+    let positionals = spec.positionals.iter().map(|p| (String::from(p.name), p.help));
+
+    let options = spec.options.iter().map(|o| match o.value {
+        Some(value) => (format!("{} {}", o.name, value), o.help),
+        None => (String::from(o.name), o.help),
+    });
+
+    positionals.chain(options).collect()
+}
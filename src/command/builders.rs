@@ -1,237 +1,837 @@
+use super::args::{Args, ArgError, Arity, Opt, Positional, Spec};
 use super::definitions::*;
-
-
-const CLEAR_FLAG: &str = "-c";
-const SINGLE_KEY_USAGE: &str = "<key>";
-const KEY_LIST_USAGE: &str = "<key> [, <key>, <key>, ... ]";
-const IMPORT_PATH: &str = "[<from_path>]";
-const EXPORT_PATH: &str = "[<export_path>]";
-const FLAG: &str = "[-c] (c - for clear)";
+use pass::context::TAG_SEPARATOR;
+
+use super::tools::{calibration, encryption::{Algorithm, EncryptionOptions, MAX_KDF_ITERATIONS, MAX_KDF_MEMORY_KIB, MAX_KDF_PARALLELISM}, merger::Strategy};
+
+
+const NO_MAX_AGE: &str = "off";
+const KIB_IN_MIB: u32 = 1024;
+const KDF_CALIBRATE: &str = "calibrate";
+const KDF_SHOW: &str = "show";
+const GIT_INIT: &str = "init";
+const GIT_PUSH: &str = "push";
+const GIT_PULL: &str = "pull";
+const GIT_LOG: &str = "log";
+const IDENTITY_CREATE: &str = "create";
+const IDENTITY_SHOW: &str = "show";
+const BACKUP_LIST: &str = "list";
+const BACKUP_RESTORE: &str = "restore";
+const CONFIG_GET: &str = "get";
+const CONFIG_SET: &str = "set";
+const CONFIG_UNSET: &str = "unset";
+const CONFIG_LIST: &str = "list";
+
+const KEY: Positional = Positional { name: "<key>", arity: Arity::Required, help: "key of the password" };
+const KEYS: Positional = Positional { name: "<key>", arity: Arity::Many, help: "keys of the passwords" };
+
+const GENERATE_OPTION: Opt = Opt { name: "--generate", value: None, help: "generate a random password instead of typing one" };
+const LENGTH_OPTION: Opt = Opt { name: "--length", value: Some("<n>"), help: "length of the generated password, from the config by default" };
+
+const KEY_FILE_OPTION: Opt = Opt { name: "--key-file", value: Some("<key_file>"), help: "key file the export is protected with" };
+const CIPHER_OPTION: Opt = Opt { name: "--cipher", value: Some("xchacha20|aes256gcm|legacy"), help: "encryption algorithm, xchacha20 by default" };
+const KDF_MEMORY_OPTION: Opt = Opt { name: "--kdf-memory", value: Some("<MiB>"), help: "Argon2id memory cost" };
+const KDF_ITERATIONS_OPTION: Opt = Opt { name: "--kdf-iterations", value: Some("<n>"), help: "Argon2id iterations" };
+const STRATEGY_OPTION: Opt = Opt { name: "--strategy", value: Some("ours|theirs|newest|fail|keep-both"), help: "resolve conflicts without asking: keep ours, take theirs, the newer one, fail, or keep both" };
+const TO_OPTION: Opt = Opt { name: "--to", value: Some("<recipients>"), help: "seal to comma-separated public keys or recipient files instead of a passphrase" };
+const TAG_OPTION: Opt = Opt { name: "--tag", value: Some("<tag>"), help: "only passwords with the tag" };
+const KDF_PARALLELISM_OPTION: Opt = Opt { name: "--kdf-parallelism", value: Some("<n>"), help: "Argon2id lanes" };
+
+const STALE_OPTION: Opt = Opt { name: "--stale", value: None, help: "only passwords due for rotation" };
+const REMOVE_TAGS_OPTION: Opt = Opt { name: "--remove", value: None, help: "remove the tags instead of adding them" };
+const DEFAULT_MAX_AGE_OPTION: Opt = Opt { name: "--default", value: None, help: "set the default of the whole vault instead of a key" };
+const REUSE_OPTION: Opt = Opt { name: "--reuse", value: None, help: "group reused and similar passwords" };
+const ROTATE_OPTION: Opt = Opt { name: "--rotate", value: None, help: "with --reuse, offer to set new passwords for each group" };
+const BREACHED_OPTION: Opt = Opt { name: "--breached", value: Some("<hibp_sha1_file>"), help: "look passwords up in a Pwned Passwords file" };
+const EXPORT_CLEAR_OPTION: Opt = Opt { name: "-c", value: None, help: "clear the passwords after exporting" };
+const FORMAT_OPTION: Opt = Opt { name: "--format", value: Some("native|kdbx"), help: "file format, native by default" };
+const KEYS_OPTION: Opt = Opt { name: "--keys", value: Some("<keys>"), help: "export only these comma-separated keys or patterns like work/*" };
+const PREFIX_OPTION: Opt = Opt { name: "--prefix", value: Some("<prefix>"), help: "export only the keys starting with the prefix, like a folder" };
+const EXPORT_TAG_OPTION: Opt = Opt { name: "--tag", value: Some("<tag>"), help: "export only the passwords with the tag" };
+const SIGN_OPTION: Opt = Opt { name: "--sign", value: None, help: "sign the export with your signing key" };
+const SHOW_VALUES_OPTION: Opt = Opt { name: "--show-values", value: None, help: "print the passwords instead of only the keys" };
+const IMPORT_CLEAR_OPTION: Opt = Opt { name: "-c", value: None, help: "replace the passwords instead of merging" };
+const NEW_KEY_FILE_OPTION: Opt = Opt { name: "--new-key-file", value: Some("<key_file>"), help: "key file to protect the export with from now on" };
+const NO_KEY_FILE_OPTION: Opt = Opt { name: "--no-key-file", value: None, help: "protect the export with the passphrase only from now on" };
+const TARGET_OPTION: Opt = Opt { name: "--target", value: Some("<time>"), help: "unlock time to calibrate for, like 1s or 500ms, 1s by default" };
+const SYNC_KEY_OPTION: Opt = Opt { name: "--key-file", value: Some("<sync_key>"), help: "for init, the sync key of an existing history" };
+const SIGNING_OPTION: Opt = Opt { name: "--signing", value: None, help: "for show, print the key exports are signed with" };
+const MERGE_OPTION: Opt = Opt { name: "--merge", value: None, help: "for restore, merge the backup in instead of replacing the store" };
+const RESTORE_SHOW_VALUES_OPTION: Opt = Opt { name: "--show-values", value: None, help: "for restore, print the passwords that differ" };
 
 pub trait CmdBuilder {
-    fn build(&self, args: Vec<String>) -> Result<Box<dyn Command>, ()>;
-    fn cmd_usage(&self) -> String;
+    /// The accepted arguments, parsed before build is called.
+    fn spec(&self) -> Spec;
+    fn build(&self, args: Args) -> Result<Box<dyn Command>, ArgError>;
 }
 
 pub struct ListBuilder;
 impl CmdBuilder for ListBuilder {
-    fn build(&self, _args: Vec<String>) -> Result<Box<dyn Command>, ()> {
-        Ok(Box::new(List))
+    fn spec(&self) -> Spec {
+        Spec {
+            options: &[
+                STALE_OPTION,
+                TAG_OPTION,
+            ],
+            positionals: &[],
+        }
+    }
+
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        Ok(Box::new(List { stale: args.flag(STALE_OPTION.name), tag: args.value(TAG_OPTION.name), output: args.output }))
+    }
+}
+
+pub struct TagBuilder;
+impl CmdBuilder for TagBuilder {
+    fn spec(&self) -> Spec {
+        Spec {
+            options: &[REMOVE_TAGS_OPTION],
+            positionals: &[
+                KEY,
+                Positional { name: "<tag>", arity: Arity::Many, help: "tags, without commas or spaces" },
+            ],
+        }
+    }
+
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        let remove = args.flag(REMOVE_TAGS_OPTION.name);
+        let key = take_one(&mut args);
+        let tags = args.rest();
+
+        if let Some(tag) = tags.iter().find(|t| t.contains(|c: char| c == TAG_SEPARATOR || c.is_whitespace())) {
+            return Err(ArgError::InvalidValue { name: "<tag>", value: tag.clone(), expected: "a tag without commas or spaces" });
+        }
+
+        Ok(Box::new(Tag { key, tags, remove }))
+    }
+}
+
+pub struct ExpiringBuilder;
+impl CmdBuilder for ExpiringBuilder {
+    fn spec(&self) -> Spec {
+        Spec {
+            options: &[],
+            positionals: &[Positional { name: "<days>", arity: Arity::Optional, help: "how far to look ahead, 14 days by default" }],
+        }
+    }
+
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        let days = match args.next() {
+            Some(days) => convert("<days>", days, "a number of days")?,
+            None => DEFAULT_EXPIRING_DAYS,
+        };
+
+        Ok(Box::new(Expiring { days, output: args.output }))
     }
+}
 
-    fn cmd_usage(&self) -> String {
-        String::new()
+pub struct MaxAgeBuilder;
+impl CmdBuilder for MaxAgeBuilder {
+    fn spec(&self) -> Spec {
+        Spec {
+            options: &[DEFAULT_MAX_AGE_OPTION],
+            positionals: &[
+                Positional { name: "<key>", arity: Arity::Optional, help: "key of the password, unless --default is given" },
+                Positional { name: "<days>", arity: Arity::Required, help: "max age in days, \"off\" to remove it" },
+            ],
+        }
+    }
+
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        let mut rest = args.rest();
+
+        let (key, days) = match (args.flag(DEFAULT_MAX_AGE_OPTION.name), rest.len()) {
+            (true, 1) => (None, rest.remove(0)),
+            (true, _) => return Err(ArgError::UnexpectedArgument(rest.remove(1))),
+            (false, 2) => (Some(rest.remove(0)), rest.remove(0)),
+            (false, _) => return Err(ArgError::MissingArgument("<days>")),
+        };
+
+        let days = match days.as_str() {
+            NO_MAX_AGE => None,
+            _ => Some(convert("<days>", days, "a number of days or \"off\"")?),
+        };
+
+        Ok(Box::new(MaxAge { key, days }))
+    }
+}
+
+pub struct AuditBuilder;
+impl CmdBuilder for AuditBuilder {
+    fn spec(&self) -> Spec {
+        Spec {
+            options: &[
+                REUSE_OPTION,
+                ROTATE_OPTION,
+                BREACHED_OPTION,
+            ],
+            positionals: &[],
+        }
+    }
+
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        let reuse = args.flag(REUSE_OPTION.name);
+        let rotate = args.flag(ROTATE_OPTION.name);
+
+        if rotate && !reuse {
+            return Err(ArgError::UnexpectedArgument(String::from(ROTATE_OPTION.name)));
+        }
+
+        Ok(Box::new(Audit { reuse, rotate, breached: args.value(BREACHED_OPTION.name) }))
     }
 }
 
 pub struct ShowBuilder;
 impl CmdBuilder for ShowBuilder {
-    fn build(&self, mut args: Vec<String>) -> Result<Box<dyn Command>, ()> {
-        build_from_one::<Show>(&mut args)
+    fn spec(&self) -> Spec {
+        Spec { options: &[], positionals: &[KEY] }
     }
 
-    fn cmd_usage(&self) -> String {
-        String::from(SINGLE_KEY_USAGE)
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        Ok(Box::new(Show { key: take_one(&mut args), output: args.output }))
     }
 }
 
 pub struct AddBuilder;
 impl CmdBuilder for AddBuilder {
-    fn build(&self, mut args: Vec<String>) -> Result<Box<dyn Command>, ()> {
-        build_from_one::<Add>(&mut args)
+    fn spec(&self) -> Spec {
+        Spec { options: &[GENERATE_OPTION, LENGTH_OPTION], positionals: &[KEY] }
     }
 
-    fn cmd_usage(&self) -> String {
-        String::from(SINGLE_KEY_USAGE)
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        let generate = take_generate(&mut args)?;
+        Ok(Box::new(Add { key: take_one(&mut args), generate }))
     }
 }
 
 pub struct RemoveBuilder;
 impl CmdBuilder for RemoveBuilder {
-    fn build(&self, mut args: Vec<String>) -> Result<Box<dyn Command>, ()> {
-        build_from_one::<Remove>(&mut args)
+    fn spec(&self) -> Spec {
+        Spec { options: &[], positionals: &[KEY] }
     }
 
-    fn cmd_usage(&self) -> String {
-        String::from(SINGLE_KEY_USAGE)
+    fn build(&self, args: Args) -> Result<Box<dyn Command>, ArgError> {
+        build_from_one::<Remove>(args)
     }
 }
 
 pub struct UpdateBuilder;
 impl CmdBuilder for UpdateBuilder {
-    fn build(&self, mut args: Vec<String>) -> Result<Box<dyn Command>, ()> {
-        build_from_one::<Update>(&mut args)
+    fn spec(&self) -> Spec {
+        Spec { options: &[GENERATE_OPTION, LENGTH_OPTION], positionals: &[KEY] }
     }
 
-    fn cmd_usage(&self) -> String {
-        String::from(SINGLE_KEY_USAGE)
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        let generate = take_generate(&mut args)?;
+        Ok(Box::new(Update { key: take_one(&mut args), generate }))
     }
 }
 
 pub struct ExportBuilder;
 impl CmdBuilder for ExportBuilder {
-    fn build(&self, mut args: Vec<String>) -> Result<Box<dyn Command>, ()> {
-        build_impexp::<Export>(&mut args)
+    fn spec(&self) -> Spec {
+        Spec {
+            options: &[
+                EXPORT_CLEAR_OPTION,
+                FORMAT_OPTION,
+                KEY_FILE_OPTION,
+                CIPHER_OPTION,
+                KDF_MEMORY_OPTION,
+                KDF_ITERATIONS_OPTION,
+                KDF_PARALLELISM_OPTION,
+                KEYS_OPTION,
+                PREFIX_OPTION,
+                EXPORT_TAG_OPTION,
+                TO_OPTION,
+                SIGN_OPTION,
+            ],
+            positionals: &[Positional { name: "<export_path>", arity: Arity::Optional, help: "where to write, next to the program by default" }],
+        }
     }
 
-    fn cmd_usage(&self) -> String {
-        format!("{} {}", EXPORT_PATH, FLAG)
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        let format = match args.value(FORMAT_OPTION.name) {
+            Some(name) => ExportFormat::from_name(&name)
+                .map_err(|_| ArgError::InvalidValue { name: FORMAT_OPTION.name, value: name, expected: "native or kdbx" })?,
+            None => ExportFormat::Native,
+        };
+
+        let to = args.value(TO_OPTION.name).map(|to| split_list(&to)).unwrap_or_default();
+        let sign = args.flag(SIGN_OPTION.name);
+
+        if let (true, ExportFormat::Kdbx) = (sign, &format) {
+            return Err(ArgError::InvalidValue { name: FORMAT_OPTION.name, value: String::from(FORMAT_KDBX), expected: "native with --sign" });
+        }
+
+        let encryption = take_encryption_options(&mut args)?;
+
+        if !to.is_empty() {
+            if let ExportFormat::Kdbx = format {
+                return Err(ArgError::InvalidValue { name: FORMAT_OPTION.name, value: String::from(FORMAT_KDBX), expected: "native with --to" });
+            }
+            if args.value(KEY_FILE_OPTION.name).is_some() {
+                return Err(ArgError::UnexpectedArgument(String::from(KEY_FILE_OPTION.name)));
+            }
+            // Age picks its own cipher and has no key derivation to tune.
+            let given = match encryption.algorithm {
+                Some(_) => Some(CIPHER_OPTION.name),
+                None => given_kdf_option(&encryption),
+            };
+
+            if let Some(name) = given {
+                return Err(ArgError::UnexpectedArgument(String::from(name)));
+            }
+        }
+
+        // KDBX files are always ChaCha20, only the key derivation can be tuned.
+        if let (ExportFormat::Kdbx, Some(_)) = (&format, encryption.algorithm) {
+            return Err(ArgError::UnexpectedArgument(String::from(CIPHER_OPTION.name)));
+        }
+
+        Ok(Box::new(Export {
+            to,
+            sign,
+            clear: args.flag(EXPORT_CLEAR_OPTION.name),
+            format,
+            key_file: args.value(KEY_FILE_OPTION.name),
+            encryption,
+            selection: Selection {
+                keys: args.value(KEYS_OPTION.name).map(|keys| split_list(&keys)).unwrap_or_default(),
+                prefix: args.value(PREFIX_OPTION.name),
+                tag: args.value(EXPORT_TAG_OPTION.name),
+            },
+            dest: args.next().unwrap_or_default(),
+        }))
+    }
+}
+
+pub struct DiffBuilder;
+impl CmdBuilder for DiffBuilder {
+    fn spec(&self) -> Spec {
+        Spec {
+            options: &[
+                KEY_FILE_OPTION,
+                SHOW_VALUES_OPTION,
+            ],
+            positionals: &[
+                Positional { name: "<path>", arity: Arity::Required, help: "export or store to compare the store with" },
+                Positional { name: "<other_path>", arity: Arity::Optional, help: "export or store to compare the first one with instead" },
+            ],
+        }
+    }
+
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        Ok(Box::new(Diff {
+            key_file: args.value(KEY_FILE_OPTION.name),
+            show_values: args.flag(SHOW_VALUES_OPTION.name),
+            output: args.output,
+            path: take_one(&mut args),
+            other_path: args.next(),
+        }))
     }
 }
 
 pub struct ImportBuilder;
 impl CmdBuilder for ImportBuilder {
-    fn build(&self, mut args: Vec<String>) -> Result<Box<dyn Command>, ()> {
-        build_impexp::<Import>(&mut args)
+    fn spec(&self) -> Spec {
+        Spec {
+            options: &[
+                IMPORT_CLEAR_OPTION,
+                KEY_FILE_OPTION,
+                STRATEGY_OPTION,
+            ],
+            positionals: &[Positional { name: "<from_path>", arity: Arity::Optional, help: "file to import, next to the program by default" }],
+        }
     }
 
-    fn cmd_usage(&self) -> String {
-        format!("{} {}", IMPORT_PATH, FLAG)
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        let strategy = parse_strategy(&mut args)?;
+
+        Ok(Box::new(Import {
+            clear: args.flag(IMPORT_CLEAR_OPTION.name),
+            key_file: args.value(KEY_FILE_OPTION.name),
+            strategy,
+            src: args.next().unwrap_or_default(),
+        }))
     }
 }
 
 pub struct RenameBuilder;
 impl CmdBuilder for RenameBuilder {
-    fn build(&self, mut args: Vec<String>) -> Result<Box<dyn Command>, ()> {
-        build_from_two::<Rename>(&mut args)
+    fn spec(&self) -> Spec {
+        Spec {
+            options: &[],
+            positionals: &[
+                Positional { name: "<old_key>", arity: Arity::Required, help: "current key" },
+                Positional { name: "<new_key>", arity: Arity::Required, help: "new key, must not exist" },
+            ],
+        }
     }
 
-    fn cmd_usage(&self) -> String {
-        String::from("<old_key> <new_key>")
+    fn build(&self, args: Args) -> Result<Box<dyn Command>, ArgError> {
+        build_from_two::<Rename>(args)
     }
 }
 
 pub struct ClearBuilder;
 impl CmdBuilder for ClearBuilder {
-    fn build(&self, mut _args: Vec<String>) -> Result<Box<dyn Command>, ()> {
-        Ok(Box::new(Clear))
+    fn spec(&self) -> Spec {
+        Spec { options: &[], positionals: &[] }
     }
 
-    fn cmd_usage(&self) -> String {
-        String::new()
+    fn build(&self, _args: Args) -> Result<Box<dyn Command>, ArgError> {
+        Ok(Box::new(Clear))
     }
 }
 
 pub struct CopyBuilder;
 impl CmdBuilder for CopyBuilder {
-    fn build(&self, mut args: Vec<String>) -> Result<Box<dyn Command>, ()> {
-        build_from_one::<Copy>(&mut args)
+    fn spec(&self) -> Spec {
+        Spec { options: &[], positionals: &[KEY] }
     }
 
-    fn cmd_usage(&self) -> String {
-        String::from(SINGLE_KEY_USAGE)
+    fn build(&self, args: Args) -> Result<Box<dyn Command>, ArgError> {
+        build_from_one::<Copy>(args)
     }
 }
 
 pub struct MultiAddBuilder;
 impl CmdBuilder for MultiAddBuilder {
-    fn build(&self, args: Vec<String>) -> Result<Box<dyn Command>, ()> {
-        build_from_list::<MultiAdd>(args)
+    fn spec(&self) -> Spec {
+        Spec { options: &[], positionals: &[KEYS] }
     }
 
-    fn cmd_usage(&self) -> String {
-        String::from(KEY_LIST_USAGE)
+    fn build(&self, args: Args) -> Result<Box<dyn Command>, ArgError> {
+        build_from_list::<MultiAdd>(args)
     }
 }
 
 pub struct MultiRemoveBuilder;
 impl CmdBuilder for MultiRemoveBuilder {
-    fn build(&self, args: Vec<String>) -> Result<Box<dyn Command>, ()> {
-        if args.len() < 1 { return Err(()); }
-        Ok(Box::new(MultiRemove{ keys: args }))
+    fn spec(&self) -> Spec {
+        Spec { options: &[], positionals: &[KEYS] }
     }
 
-    fn cmd_usage(&self) -> String {
-        String::from(KEY_LIST_USAGE)
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        Ok(Box::new(MultiRemove{ keys: args.rest() }))
     }
 }
 
 pub struct MultiUpdateBuilder;
 impl CmdBuilder for MultiUpdateBuilder {
-    fn build(&self, args: Vec<String>) -> Result<Box<dyn Command>, ()> {
+    fn spec(&self) -> Spec {
+        Spec { options: &[], positionals: &[KEYS] }
+    }
+
+    fn build(&self, args: Args) -> Result<Box<dyn Command>, ArgError> {
         build_from_list::<MultiUpdate>(args)
     }
+}
 
-    fn cmd_usage(&self) -> String {
-        String::from(KEY_LIST_USAGE)
+pub struct RekeyBuilder;
+impl CmdBuilder for RekeyBuilder {
+    fn spec(&self) -> Spec {
+        Spec {
+            options: &[
+                KEY_FILE_OPTION,
+                NEW_KEY_FILE_OPTION,
+                NO_KEY_FILE_OPTION,
+                CIPHER_OPTION,
+                KDF_MEMORY_OPTION,
+                KDF_ITERATIONS_OPTION,
+                KDF_PARALLELISM_OPTION,
+            ],
+            positionals: &[Positional { name: "<export_path>", arity: Arity::Optional, help: "export to change, next to the program by default" }],
+        }
+    }
+
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        let new_key_file = match (args.value(NEW_KEY_FILE_OPTION.name), args.flag(NO_KEY_FILE_OPTION.name)) {
+            (Some(_), true) => return Err(ArgError::UnexpectedArgument(String::from(NO_KEY_FILE_OPTION.name))),
+            (Some(path), false) => NewKeyFile::Path(path),
+            (None, true) => NewKeyFile::None,
+            (None, false) => NewKeyFile::Same,
+        };
+
+        Ok(Box::new(Rekey {
+            key_file: args.value(KEY_FILE_OPTION.name),
+            new_key_file,
+            encryption: take_encryption_options(&mut args)?,
+            path: args.next().unwrap_or_default(),
+        }))
+    }
+}
+
+pub struct KdfBuilder;
+impl CmdBuilder for KdfBuilder {
+    fn spec(&self) -> Spec {
+        Spec {
+            options: &[TARGET_OPTION],
+            positionals: &[Positional { name: "calibrate|show", arity: Arity::Required, help: "measure and save the parameters, or show the current ones" }],
+        }
+    }
+
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        let target = args.value(TARGET_OPTION.name);
+
+        match (take_one(&mut args).as_str(), target) {
+            (KDF_CALIBRATE, target) => {
+                let target = target.unwrap_or_else(|| String::from(DEFAULT_CALIBRATION_TARGET));
+                let target = calibration::parse_duration(&target)
+                    .ok_or(ArgError::InvalidValue { name: TARGET_OPTION.name, value: target, expected: "a time like 1s or 500ms" })?;
+
+                Ok(Box::new(KdfCalibrate { target }))
+            },
+            (KDF_SHOW, None) => Ok(Box::new(KdfShow { output: args.output })),
+            (KDF_SHOW, Some(_)) => Err(ArgError::UnexpectedArgument(String::from(TARGET_OPTION.name))),
+            (action, _) => Err(ArgError::InvalidValue { name: "action", value: String::from(action), expected: "calibrate or show" }),
+        }
+    }
+}
+
+pub struct KeyGenBuilder;
+impl CmdBuilder for KeyGenBuilder {
+    fn spec(&self) -> Spec {
+        Spec {
+            options: &[],
+            positionals: &[Positional { name: "<key_file_path>", arity: Arity::Required, help: "where to create the key file" }],
+        }
+    }
+
+    fn build(&self, args: Args) -> Result<Box<dyn Command>, ArgError> {
+        build_from_one::<KeyGen>(args)
     }
 }
 
 pub struct PasteBuilder;
 impl CmdBuilder for PasteBuilder {
-    fn build(&self, mut args: Vec<String>) -> Result<Box<dyn Command>, ()> {
-        build_from_one::<Paste>(&mut args)
+    fn spec(&self) -> Spec {
+        Spec { options: &[], positionals: &[KEY] }
+    }
+
+    fn build(&self, args: Args) -> Result<Box<dyn Command>, ArgError> {
+        build_from_one::<Paste>(args)
+    }
+}
+
+pub struct CompletionsBuilder;
+impl CmdBuilder for CompletionsBuilder {
+    fn spec(&self) -> Spec {
+        Spec {
+            options: &[],
+            positionals: &[Positional { name: "bash|zsh|fish", arity: Arity::Required, help: "shell to complete for" }],
+        }
     }
 
-    fn cmd_usage(&self) -> String {
-        String::from(SINGLE_KEY_USAGE)
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        let shell = convert("shell", take_one(&mut args), "bash, zsh or fish")?;
+        Ok(Box::new(Completions { shell }))
     }
 }
 
-fn unpack_one(args: &mut Vec<String>, index: usize) -> String {
-    std::mem::replace(&mut args[index], String::new())
+pub struct ConfigBuilder;
+impl CmdBuilder for ConfigBuilder {
+    fn spec(&self) -> Spec {
+        Spec {
+            options: &[],
+            positionals: &[
+                Positional { name: "get|set|unset|list", arity: Arity::Required, help: "read, change, remove or list settings" },
+                Positional { name: "<setting>", arity: Arity::Optional, help: "dotted name like store.path or aliases.cp" },
+                Positional { name: "<value>", arity: Arity::Optional, help: "new value, for set" },
+            ],
+        }
+    }
+
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        let action = take_one(&mut args);
+        let key = args.next();
+        let value = args.next();
+
+        match (action.as_str(), key, value) {
+            (CONFIG_LIST, None, _) => Ok(Box::new(ConfigList { output: args.output })),
+            (CONFIG_GET, Some(key), None) => Ok(Box::new(ConfigGet { key })),
+            (CONFIG_SET, Some(key), Some(value)) => Ok(Box::new(ConfigSet { key, value })),
+            (CONFIG_UNSET, Some(key), None) => Ok(Box::new(ConfigUnset { key })),
+            (CONFIG_LIST, Some(extra), _) | (CONFIG_GET, _, Some(extra)) | (CONFIG_UNSET, _, Some(extra)) => {
+                Err(ArgError::UnexpectedArgument(extra))
+            },
+            (CONFIG_SET, Some(_), None) => Err(ArgError::MissingArgument("<value>")),
+            (CONFIG_GET, None, _) | (CONFIG_SET, None, _) | (CONFIG_UNSET, None, _) => Err(ArgError::MissingArgument("<setting>")),
+            (action, _, _) => Err(ArgError::InvalidValue { name: "action", value: String::from(action), expected: "get, set, unset or list" }),
+        }
+    }
 }
 
-fn unpack_two(args: &mut Vec<String>) -> (String, String) {
-    let first = std::mem::replace(&mut args[0], String::new());
-    let second = std::mem::replace(&mut args[1], String::new());
-    (first, second)
+pub struct GitBuilder;
+impl CmdBuilder for GitBuilder {
+    fn spec(&self) -> Spec {
+        Spec {
+            options: &[SYNC_KEY_OPTION],
+            positionals: &[
+                Positional { name: "init|push|pull|log", arity: Arity::Required, help: "create, send, receive or show the history" },
+                Positional { name: "<remote>", arity: Arity::Optional, help: "for init, a path or URL of a bare repository to sync with" },
+            ],
+        }
+    }
+
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        let action = take_one(&mut args);
+        let remote = args.next();
+        let key_file = args.value(SYNC_KEY_OPTION.name);
+
+        if action != GIT_INIT {
+            if let Some(extra) = remote {
+                return Err(ArgError::UnexpectedArgument(extra));
+            }
+            if key_file.is_some() {
+                return Err(ArgError::UnexpectedArgument(String::from(SYNC_KEY_OPTION.name)));
+            }
+        }
+
+        match action.as_str() {
+            GIT_INIT => Ok(Box::new(GitInit { remote, key_file })),
+            GIT_PUSH => Ok(Box::new(GitPush)),
+            GIT_PULL => Ok(Box::new(GitPull)),
+            GIT_LOG => Ok(Box::new(GitLog)),
+            _ => Err(ArgError::InvalidValue { name: "action", value: action, expected: "init, push, pull or log" }),
+        }
+    }
 }
 
-fn build_from_one<T>(args: &mut Vec<String>) -> Result<Box<dyn Command>, ()>
-    where T: 'static + From::<String> + Command {
-    match args.len() >= 1 {
-        true => Ok(Box::new(T::from(unpack_one(args, 0)))),
-        false => Err(()),
+pub struct IdentityBuilder;
+impl CmdBuilder for IdentityBuilder {
+    fn spec(&self) -> Spec {
+        Spec {
+            options: &[SIGNING_OPTION],
+            positionals: &[Positional { name: "create|show", arity: Arity::Required, help: "generate your keys or print a public key" }],
+        }
+    }
+
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        let action = take_one(&mut args);
+        let signing = args.flag(SIGNING_OPTION.name);
+
+        match action.as_str() {
+            IDENTITY_CREATE if signing => Err(ArgError::UnexpectedArgument(String::from(SIGNING_OPTION.name))),
+            IDENTITY_CREATE => Ok(Box::new(IdentityCreate)),
+            IDENTITY_SHOW => Ok(Box::new(IdentityShow { signing })),
+            _ => Err(ArgError::InvalidValue { name: "action", value: action, expected: "create or show" }),
+        }
     }
 }
 
-fn build_from_two<T>(args: &mut Vec<String>) -> Result<Box<dyn Command>, ()>
-    where T: 'static + From::<(String, String)> + Command {
-    match args.len() >= 2 {
-        true => {
-            let (first, second) = unpack_two(args);
-            Ok(Box::new(T::from((first, second))))
+pub struct BackupBuilder;
+impl CmdBuilder for BackupBuilder {
+    fn spec(&self) -> Spec {
+        Spec {
+            options: &[
+                MERGE_OPTION,
+                STRATEGY_OPTION,
+                RESTORE_SHOW_VALUES_OPTION,
+            ],
+            positionals: &[
+                Positional { name: "list|restore", arity: Arity::Required, help: "show the backups or put one back" },
+                Positional { name: "<id>", arity: Arity::Optional, help: "for restore, the backup as listed" },
+            ],
+        }
+    }
+
+    fn build(&self, mut args: Args) -> Result<Box<dyn Command>, ArgError> {
+        let action = take_one(&mut args);
+        let id = args.next();
+        let strategy = parse_strategy(&mut args)?;
+        let merge = args.flag(MERGE_OPTION.name);
+        let show_values = args.flag(RESTORE_SHOW_VALUES_OPTION.name);
+
+        match action.as_str() {
+            BACKUP_LIST => {
+                let restore_option = [(MERGE_OPTION.name, merge), (STRATEGY_OPTION.name, strategy.is_some()), (RESTORE_SHOW_VALUES_OPTION.name, show_values)]
+                    .iter().find(|(_, given)| *given).map(|&(name, _)| name);
+
+                match (id, restore_option) {
+                    (Some(extra), _) => Err(ArgError::UnexpectedArgument(extra)),
+                    (None, Some(name)) => Err(ArgError::UnexpectedArgument(String::from(name))),
+                    (None, None) => Ok(Box::new(BackupList { output: args.output })),
+                }
+            },
+            BACKUP_RESTORE => match id {
+                Some(id) => Ok(Box::new(BackupRestore { id, merge, strategy, show_values })),
+                None => Err(ArgError::MissingArgument("<id>")),
+            },
+            _ => Err(ArgError::InvalidValue { name: "action", value: action, expected: "list or restore" }),
         }
-        false => Err(()),
     }
 }
 
-fn build_from_list<T>(args: Vec<String>) -> Result<Box<dyn Command>, ()>
-    where T: 'static + From::<Vec<String>> + Command {
-    match args.len() < 1 {
-        true => Err(()),
-        false => Ok(Box::new(T::from(args))), 
-    } 
+pub struct RecoverBuilder;
+impl CmdBuilder for RecoverBuilder {
+    fn spec(&self) -> Spec {
+        Spec { options: &[], positionals: &[] }
+    }
+
+    fn build(&self, _args: Args) -> Result<Box<dyn Command>, ArgError> {
+        Ok(Box::new(Recover))
+    }
 }
 
-fn build_impexp<T>(args: &mut Vec<String>) -> Result<Box<dyn Command>, ()>
-    where T: 'static + From::<(String, bool)> + Command {
-    if args.len() > 2 {
-        return Err(());
+fn parse_strategy(args: &mut Args) -> Result<Option<Strategy>, ArgError> {
+    match args.value(STRATEGY_OPTION.name) {
+        Some(name) => Strategy::from_name(&name)
+            .map(Some)
+            .ok_or(ArgError::InvalidValue { name: STRATEGY_OPTION.name, value: name, expected: "ours, theirs, newest, fail or keep-both" }),
+        None => Ok(None),
     }
+}
 
-    let mut flags_count = 0;
-    let mut path_idxs = vec![];
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect()
+}
 
-    for (idx, arg) in args.iter().enumerate() {
-        match arg.as_str() {
-            CLEAR_FLAG => flags_count += 1,
-            _ => path_idxs.push(idx),
-        }
+// Required positionals are checked by the parser.
+fn take_one(args: &mut Args) -> String {
+    args.next().unwrap_or_default()
+}
+
+fn build_from_one<T>(mut args: Args) -> Result<Box<dyn Command>, ArgError>
+    where T: 'static + From::<String> + Command {
+    Ok(Box::new(T::from(take_one(&mut args))))
+}
+
+fn build_from_two<T>(mut args: Args) -> Result<Box<dyn Command>, ArgError>
+    where T: 'static + From::<(String, String)> + Command {
+    let first = take_one(&mut args);
+    let second = take_one(&mut args);
+    Ok(Box::new(T::from((first, second))))
+}
+
+fn build_from_list<T>(mut args: Args) -> Result<Box<dyn Command>, ArgError>
+    where T: 'static + From::<Vec<String>> + Command {
+    Ok(Box::new(T::from(args.rest())))
+}
+
+fn convert<T: std::str::FromStr>(name: &'static str, value: String, expected: &'static str) -> Result<T, ArgError> {
+    value.parse().map_err(|_| ArgError::InvalidValue { name, value, expected })
+}
+
+fn take_generate(args: &mut Args) -> Result<Option<Generate>, ArgError> {
+    let length = args.parse(LENGTH_OPTION.name, "a number of characters")?;
+
+    match (args.flag(GENERATE_OPTION.name), length) {
+        (true, length) => Ok(Some(Generate { length })),
+        (false, None) => Ok(None),
+        (false, Some(_)) => Err(ArgError::UnexpectedArgument(String::from(LENGTH_OPTION.name))),
     }
+}
+
+fn take_encryption_options(args: &mut Args) -> Result<EncryptionOptions, ArgError> {
+    let algorithm = match args.value(CIPHER_OPTION.name) {
+        Some(name) => Some(Algorithm::from_name(&name)
+            .ok_or(ArgError::InvalidValue { name: CIPHER_OPTION.name, value: name, expected: "xchacha20, aes256gcm or legacy" })?),
+        None => None,
+    };
 
-    let clear = match flags_count {
-        0 => false,
-        1 => true,
-        _ => return Err(()),
+    let memory_kib = match args.value(KDF_MEMORY_OPTION.name) {
+        Some(memory) => Some(convert::<u32>(KDF_MEMORY_OPTION.name, memory.clone(), "a number of MiB")?
+            .checked_mul(KIB_IN_MIB)
+            .filter(|kib| *kib <= MAX_KDF_MEMORY_KIB)
+            .ok_or(ArgError::InvalidValue { name: KDF_MEMORY_OPTION.name, value: memory, expected: "at most 4096 MiB" })?),
+        None => None,
     };
 
-    let path = match path_idxs.len() {
-        0 => String::new(),
-        1 => unpack_one(args, path_idxs[0]),
-        _ => return Err(()),
+    let options = EncryptionOptions {
+        algorithm,
+        memory_kib,
+        iterations: at_most(args, KDF_ITERATIONS_OPTION.name, MAX_KDF_ITERATIONS, "a number up to 64")?,
+        parallelism: at_most(args, KDF_PARALLELISM_OPTION.name, MAX_KDF_PARALLELISM, "a number up to 16")?,
     };
 
-    Ok(Box::new(T::from((path, clear))))
+    // The legacy format derives its key with PBKDF2, the Argon2id costs would be ignored.
+    if let (Some(Algorithm::Tindercrypt), Some(name)) = (options.algorithm, given_kdf_option(&options)) {
+        return Err(ArgError::UnexpectedArgument(String::from(name)));
+    }
+
+    Ok(options)
+}
+
+/// The first of the Argon2id options given, if any.
+fn given_kdf_option(options: &EncryptionOptions) -> Option<&'static str> {
+    [
+        (KDF_MEMORY_OPTION.name, options.memory_kib.is_some()),
+        (KDF_ITERATIONS_OPTION.name, options.iterations.is_some()),
+        (KDF_PARALLELISM_OPTION.name, options.parallelism.is_some()),
+    ].iter().find(|(_, given)| *given).map(|&(name, _)| name)
+}
+
+fn at_most(args: &mut Args, name: &'static str, max: u32, expected: &'static str) -> Result<Option<u32>, ArgError> {
+    match args.value(name) {
+        Some(value) => convert::<u32>(name, value.clone(), expected)
+            .and_then(|n| if n <= max { Ok(Some(n)) } else { Err(ArgError::InvalidValue { name, value, expected }) }),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::args;
+    use super::*;
+
+    fn build(builder: &dyn CmdBuilder, arguments: &[&str]) -> Result<Box<dyn Command>, ArgError> {
+        let arguments = arguments.iter().map(|a| String::from(*a)).collect();
+        args::parse(&builder.spec(), arguments).and_then(|args| builder.build(args))
+    }
+
+    fn unexpected(result: Result<Box<dyn Command>, ArgError>) -> Option<String> {
+        match result {
+            Err(ArgError::UnexpectedArgument(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn legacy_with_kdf_options() {
+        for option in &["--kdf-memory", "--kdf-iterations", "--kdf-parallelism"] {
+            let export = build(&ExportBuilder, &["--cipher", "legacy", option, "2"]);
+            assert_eq!(unexpected(export).as_deref(), Some(*option));
+
+            let rekey = build(&RekeyBuilder, &["export", "--cipher", "legacy", option, "2"]);
+            assert_eq!(unexpected(rekey).as_deref(), Some(*option));
+        }
+
+        assert!(build(&ExportBuilder, &["--cipher", "legacy"]).is_ok());
+        assert!(build(&ExportBuilder, &["--cipher", "aes256gcm", "--kdf-memory", "32"]).is_ok());
+    }
+
+    #[test]
+    fn sealed_with_passphrase_options() {
+        let key = "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p";
+
+        for option in &["--cipher", "--kdf-memory", "--key-file"] {
+            let value = if *option == "--cipher" { "xchacha20" } else { "2" };
+            assert_eq!(unexpected(build(&ExportBuilder, &["--to", key, option, value])).as_deref(), Some(*option));
+        }
+
+        assert!(build(&ExportBuilder, &["--to", key, "-c"]).is_ok());
+    }
+
+    #[test]
+    fn kdbx_with_cipher() {
+        assert_eq!(unexpected(build(&ExportBuilder, &["--format", "kdbx", "--cipher", "aes256gcm"])).as_deref(), Some("--cipher"));
+        assert!(build(&ExportBuilder, &["--format", "kdbx", "--kdf-iterations", "2"]).is_ok());
+    }
+
+    #[test]
+    fn kdf_limits() {
+        assert!(build(&ExportBuilder, &["--kdf-memory", "4096", "--kdf-iterations", "64", "--kdf-parallelism", "16"]).is_ok());
+
+        for (option, value) in &[("--kdf-memory", "4097"), ("--kdf-memory", "4194304"), ("--kdf-iterations", "65"), ("--kdf-parallelism", "17")] {
+            assert!(matches!(build(&ExportBuilder, &[option, value]), Err(ArgError::InvalidValue { name, .. }) if name == *option));
+        }
+    }
+
+    #[test]
+    fn rotate_needs_reuse() {
+        assert_eq!(unexpected(build(&AuditBuilder, &["--rotate"])).as_deref(), Some("--rotate"));
+        assert!(build(&AuditBuilder, &["--reuse", "--rotate"]).is_ok());
+    }
 }
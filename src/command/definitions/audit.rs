@@ -0,0 +1,242 @@
+use pass::context::{Context, PassListModel};
+use pass::error::Error;
+
+use super::{
+    Command,
+    MultiUpdate,
+    tools::{msg, strength, breach::BreachIndex},
+};
+
+const MIN_LENGTH: usize = 12;
+const MIN_SCORE: u8 = 3;
+const MAX_SCORE: u8 = 4;
+const SIMILAR_DISTANCE: usize = 2;
+const SIMILAR_MIN_LENGTH: usize = 6;
+
+pub const EXIT_CLEAN: i32 = 0;
+pub const EXIT_WARNINGS: i32 = 1;
+pub const EXIT_WEAK: i32 = 2;
+pub const EXIT_BREACHED: i32 = 3;
+
+const BREACH_FILE: &str = "Pwned Passwords SHA-1 file ordered by hash";
+
+pub struct Audit {
+    pub reuse: bool,
+    /// Offer to set new passwords for the reused ones, asking for each group.
+    pub rotate: bool,
+    pub breached: Option<String>,
+}
+
+impl Command for Audit {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let mut codes = vec![];
+
+        if self.reuse {
+            codes.push(audit_reuse(context, self.rotate)?);
+        }
+
+        if let Some(path) = &self.breached {
+            codes.push(audit_breached(context, path)?);
+        }
+
+        if codes.is_empty() {
+            codes.push(audit_strength(context));
+        }
+
+        context.exit_code = codes.into_iter().max().unwrap_or(EXIT_CLEAN);
+
+        Ok(())
+    }
+}
+
+fn audit_strength(context: &mut Context) -> i32 {
+    let mut keys = context.model.keys().collect::<Vec<&String>>();
+    keys.sort();
+
+    let (mut weak, mut short, mut patterned, mut total_score) = (0, 0, 0, 0);
+
+    for key in keys.iter() {
+        let estimate = strength::estimate(context.model[*key].value.expose());
+        let mut issues = vec![];
+
+        if estimate.score < MIN_SCORE {
+            weak += 1;
+            issues.push(String::from("weak"));
+        }
+
+        if estimate.length < MIN_LENGTH {
+            short += 1;
+            issues.push(format!("short ({} chars)", estimate.length));
+        }
+
+        if !estimate.patterns.is_empty() {
+            patterned += 1;
+            let names = estimate.patterns.iter().map(|p| p.name()).collect::<Vec<&str>>();
+            issues.push(format!("contains {}", names.join(", ")));
+        }
+
+        total_score += estimate.score as usize;
+
+        if issues.is_empty() { continue; }
+
+        println!("{:20} score {}/{}, cracked in {}: {}",
+            key, estimate.score, MAX_SCORE, strength::format_duration(estimate.crack_seconds()), issues.join("; "));
+    }
+
+    let average = if keys.is_empty() { 0.0 } else { total_score as f64 / keys.len() as f64 };
+    msg::audit_summary(keys.len(), weak, short, patterned, average, MAX_SCORE);
+
+    match (weak, short + patterned) {
+        (0, 0) => EXIT_CLEAN,
+        (0, _) => EXIT_WARNINGS,
+        _ => EXIT_WEAK,
+    }
+}
+
+fn audit_breached(context: &Context, path: &str) -> Result<i32, Error> {
+    let mut index = BreachIndex::open(path).map_err(|err| Error::read(path, err))?;
+
+    let mut keys = context.model.keys().collect::<Vec<&String>>();
+    keys.sort();
+
+    let mut breached = 0;
+
+    for key in keys.iter() {
+        match index.lookup(context.model[*key].value.expose()) {
+            Ok(Some(count)) => {
+                breached += 1;
+                println!("{:20} seen {} times in breaches", key, count);
+            },
+            Ok(None) => (),
+            Err(_) => return Err(Error::parse(path, BREACH_FILE)),
+        }
+    }
+
+    msg::breach_summary(keys.len(), breached);
+
+    Ok(if breached > 0 { EXIT_BREACHED } else { EXIT_CLEAN })
+}
+
+struct ReuseGroup {
+    keys: Vec<String>,
+    identical: bool,
+}
+
+// The whole report comes first, so it's complete even when rotating stops halfway.
+fn audit_reuse(context: &mut Context, rotate: bool) -> Result<i32, Error> {
+    let groups = find_reuse_groups(&context.model);
+
+    if groups.is_empty() {
+        msg::no_reused_passwords();
+        return Ok(EXIT_CLEAN);
+    }
+
+    for group in groups.iter() {
+        let kind = if group.identical { "Identical" } else { "Similar" };
+        println!("{} passwords: {}", kind, group.keys.join(", "));
+    }
+
+    if rotate {
+        for group in groups.into_iter() {
+            msg::rotate_group(&group.keys);
+            if context.prompt.confirm(msg::strings::ROTATE_GROUP)? {
+                Box::new(MultiUpdate::from(group.keys)).execute(context)?;
+            }
+        }
+    }
+
+    Ok(EXIT_WARNINGS)
+}
+
+fn find_reuse_groups(model: &PassListModel) -> Vec<ReuseGroup> {
+    let mut keys = model.keys().collect::<Vec<&String>>();
+    keys.sort();
+
+    // every key points to the first key of its group
+    let mut parents = (0..keys.len()).collect::<Vec<usize>>();
+
+    for i in 0..keys.len() {
+        for j in i + 1..keys.len() {
+            if parents[j] != j { continue; }
+
+            let (first, second) = (&model[keys[i]].value, &model[keys[j]].value);
+            if first == second || are_similar(first.expose(), second.expose()) {
+                parents[j] = parents[i];
+            }
+        }
+    }
+
+    let mut groups = vec![];
+
+    for (root, key) in keys.iter().enumerate().filter(|(idx, _)| parents[*idx] == *idx) {
+        let members = keys.iter().enumerate()
+            .filter(|(idx, _)| parents[*idx] == root)
+            .map(|(_, k)| (*k).clone())
+            .collect::<Vec<String>>();
+
+        if members.len() < 2 { continue; }
+
+        groups.push(ReuseGroup {
+            identical: members.iter().all(|k| model[k].value == model[*key].value),
+            keys: members,
+        });
+    }
+
+    groups
+}
+
+fn are_similar(first: &str, second: &str) -> bool {
+    first.chars().count().min(second.chars().count()) >= SIMILAR_MIN_LENGTH
+        && strength::edit_distance(first, second) <= SIMILAR_DISTANCE
+}
+
+#[cfg(test)]
+mod tests {
+    use pass::config::Config;
+    use pass::context::Entry;
+    use pass::error::InputError;
+    use pass::secret::SecretString;
+
+    use super::*;
+
+    fn context(entries: &[(&str, &str)]) -> Context {
+        let mut context = Context::unopened("unused", Config::default());
+        for &(key, value) in entries {
+            context.model.insert(String::from(key), Entry::new(SecretString::from(value)));
+        }
+        context
+    }
+
+    #[test]
+    fn reuse_without_prompt() {
+        let mut context = context(&[("mail", "correct horse"), ("bank", "correct horse"), ("shop", "battery staple"), ("forum", "battery staples")]);
+
+        assert_eq!(audit_reuse(&mut context, false).unwrap(), EXIT_WARNINGS);
+
+        Box::new(Audit { reuse: true, rotate: false, breached: None }).execute(&mut context).unwrap();
+        assert_eq!(context.exit_code, EXIT_WARNINGS);
+    }
+
+    #[test]
+    fn no_reuse() {
+        let mut context = context(&[("mail", "correct horse"), ("bank", "battery staple")]);
+        assert_eq!(audit_reuse(&mut context, false).unwrap(), EXIT_CLEAN);
+    }
+
+    #[test]
+    fn rotate_needs_prompt() {
+        let mut context = context(&[("mail", "correct horse"), ("bank", "correct horse")]);
+        assert!(matches!(audit_reuse(&mut context, true), Err(Error::Input(InputError::NotInteractive))));
+    }
+
+    #[test]
+    fn groups() {
+        let context = context(&[("mail", "correct horse"), ("bank", "correct horse"), ("shop", "battery staple"), ("forum", "battery staples"), ("other", "unrelated")]);
+        let groups = find_reuse_groups(&context.model)
+            .into_iter()
+            .map(|group| (group.keys, group.identical))
+            .collect::<Vec<(Vec<String>, bool)>>();
+
+        assert_eq!(groups, vec![(vec![String::from("bank"), String::from("mail")], true), (vec![String::from("forum"), String::from("shop")], false)]);
+    }
+}
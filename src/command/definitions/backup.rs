@@ -0,0 +1,111 @@
+use pass::config::Config;
+use pass::context::Context;
+use pass::error::Error;
+use pass::merger::Strategy;
+use pass::tools::backup;
+
+use serde::Serialize;
+
+use super::{
+    Command,
+    StoreAccess,
+    args::OutputFormat,
+    diff::preview,
+    tools::msg,
+};
+
+const STORE_NAME: &str = "the store";
+
+/// Prints the backups of the store, newest first.
+pub struct BackupList {
+    pub output: OutputFormat,
+}
+
+#[derive(Serialize)]
+struct Listed {
+    id: String,
+    time: u64,
+    /// None when the backup is damaged.
+    passwords: Option<usize>,
+}
+
+impl Command for BackupList {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let backups = backup::list(&context.data_file_path)?;
+
+        let listed = backups.iter().map(|b| {
+            let passwords = match Context::open(&b.path.to_string_lossy(), Config::default()) {
+                Ok(backup) => Some(backup.model.len()),
+                Err(Error::Damaged { .. }) => None,
+                Err(err) => return Err(err),
+            };
+            Ok(Listed { id: b.id.clone(), time: b.time, passwords })
+        }).collect::<Result<Vec<Listed>, Error>>()?;
+
+        match self.output {
+            OutputFormat::Text if listed.is_empty() => msg::no_backups(),
+            OutputFormat::Text => backups.iter().zip(&listed)
+                .for_each(|(b, l)| match l.passwords {
+                    Some(n) => println!("{:20} {} UTC  {} passwords", b.id, b.date(), n),
+                    None => println!("{:20} {} UTC  damaged", b.id, b.date()),
+                }),
+            OutputFormat::Json => msg::json(&listed),
+        }
+
+        Ok(())
+    }
+
+    fn records_history(&self) -> bool {
+        false
+    }
+
+    fn store_access(&self) -> StoreAccess {
+        StoreAccess::Unused
+    }
+}
+
+/// Shows how a backup differs from the store, then puts it back in place of the store
+/// or merges it in. The replaced store is backed up in turn.
+pub struct BackupRestore {
+    pub id: String,
+    pub merge: bool,
+    pub strategy: Option<Strategy>,
+    pub show_values: bool,
+}
+
+impl Command for BackupRestore {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let found = backup::find(&context.data_file_path, &self.id)?;
+        let restored = Context::open(&found.path.to_string_lossy(), Config::default())?;
+
+        let backup_name = format!("backup {}", found.id);
+        let differs = preview(STORE_NAME, &context.model, &backup_name, &restored.model, self.show_values);
+
+        if !differs && restored.settings.max_age == context.settings.max_age {
+            msg::same_as_backup(&found.id);
+            return Ok(());
+        }
+
+        if let Some(strategy) = self.strategy {
+            context.resolver = Box::new(strategy);
+        }
+
+        if self.merge || self.strategy.is_some() {
+            if let Some(summary) = context.merge(restored.model)? {
+                msg::merge_summary(&summary);
+            }
+            return Ok(());
+        }
+
+        if context.confirm(msg::strings::RESTORE)? {
+            context.model = restored.model;
+            context.settings = restored.settings;
+        }
+
+        Ok(())
+    }
+
+    fn store_access(&self) -> StoreAccess {
+        StoreAccess::Salvaged
+    }
+}
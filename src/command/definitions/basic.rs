@@ -1,64 +1,105 @@
-use crate::context::Context;
+use pass::context::Context;
+use pass::error::{Error, InputError};
+use pass::secret::SecretString;
 
 use super::{
     Command,
-    tools::{msg, dialog},
+    expiry,
+    args::OutputFormat,
+    tools::{msg, generator},
 };
 
+use std::thread;
+use std::time::Duration;
+
 use clipboard::{ClipboardContext, ClipboardProvider};
 
-pub struct List;
+pub struct List {
+    pub stale: bool,
+    /// Only the keys with the tag.
+    pub tag: Option<String>,
+    pub output: OutputFormat,
+}
 
 impl Command for List {
-    fn execute(self: Box<Self>, context: &mut Context) {
-        let mut list = context.model.iter()
-            .map(|(key, _value)| key)
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        if self.stale {
+            expiry::print_due(&expiry::due_entries(context, 0), self.output);
+            return Ok(());
+        }
+
+        let keys = context.keys().into_iter()
+            .filter(|key| self.tag.as_ref().is_none_or(|tag| context.model[*key].has_tag(tag)))
             .collect::<Vec<&String>>();
 
-        list.sort();
-        
-        list.iter().for_each(|e| println!("{}", e));
+        match self.output {
+            OutputFormat::Text => keys.iter().for_each(|e| println!("{}", e)),
+            OutputFormat::Json => msg::json(&keys),
+        }
+
+        Ok(())
     }
 }
 
-pub struct Show {
+pub struct Tag {
     pub key: String,
+    pub tags: Vec<String>,
+    pub remove: bool,
 }
 
-impl Command for Show {
-    fn execute(self: Box<Self>, context: &mut Context) {
-        match context.model.get(&self.key) {
-            Some(pass) => println!("{}", pass),
-            None => msg::no_such_key(),
+impl Command for Tag {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let entry = context.model.get_mut(&self.key).ok_or_else(|| Error::NotFound(self.key.clone()))?;
+
+        match self.remove {
+            true => entry.tags.retain(|tag| !self.tags.contains(tag)),
+            false => {
+                entry.tags.extend(self.tags);
+                entry.tags.sort();
+                entry.tags.dedup();
+            },
         }
+
+        Ok(())
     }
 }
 
-impl From<String> for Show {
-    fn from(key: String) -> Show {
-        Show { key }
+pub struct Show {
+    pub key: String,
+    pub output: OutputFormat,
+}
+
+impl Command for Show {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let entry = context.get(&self.key)?;
+
+        match self.output {
+            OutputFormat::Text => println!("{}", entry.value.expose()),
+            OutputFormat::Json => msg::json(&serde_json::json!({ "key": self.key, "value": entry.value.expose() })),
+        }
+
+        Ok(())
     }
 }
 
 pub struct Add {
     pub key: String,
+    pub generate: Option<Generate>,
+}
+
+/// Asks for a generated password instead of a typed one.
+pub struct Generate {
+    pub length: Option<usize>,
 }
 
 impl Command for Add {
-    fn execute(self: Box<Self>, context: &mut Context) {
-        match context.model.contains_key(&self.key) {
-            true => msg::already_exist(),
-            false => match dialog::ask_for_password(true) {
-                Ok(pass) => { context.model.insert(self.key, pass); },
-                Err(err) => msg::pass_read_error(err),
-            }
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        if context.model.contains_key(&self.key) {
+            return Err(Error::AlreadyExists(self.key));
         }
-    }
-}
 
-impl From<String> for Add {
-    fn from(key: String) -> Add {
-        Add { key }
+        let pass = new_password(context, self.generate)?;
+        context.add(self.key, pass)
     }
 }
 
@@ -67,15 +108,14 @@ pub struct Remove {
 }
 
 impl Command for Remove {
-    fn execute(self: Box<Self>, context: &mut Context) {
-        match context.model.contains_key(&self.key) {
-            true => match dialog::confirm(msg::strings::RM) {
-                Ok(true) => { context.model.remove(&self.key); },
-                Err(_) => msg::input_failed(),
-                _ => (),
-            },
-            false => msg::no_such_key(),
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        context.get(&self.key)?;
+
+        if context.confirm(msg::strings::RM)? {
+            context.remove(&self.key)?;
         }
+
+        Ok(())
     }
 }
 
@@ -87,23 +127,15 @@ impl From<String> for Remove {
 
 pub struct Update {
     pub key: String,
+    pub generate: Option<Generate>,
 }
 
 impl Command for Update {
-    fn execute(self: Box<Self>, context: &mut Context) {
-        match context.model.contains_key(&self.key) {
-            true => match dialog::ask_for_password(true) {
-                Ok(pass) => { context.model.insert(self.key, pass); },
-                Err(err) => msg::pass_read_error(err),
-            },
-            false => msg::no_such_key(),
-        }
-    }
-}
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        context.get(&self.key)?;
 
-impl From<String> for Update {
-    fn from(key: String) -> Update {
-        Update { key }
+        let pass = new_password(context, self.generate)?;
+        context.update(&self.key, pass)
     }
 }
 
@@ -113,19 +145,8 @@ pub struct Rename {
 }
 
 impl Command for Rename {
-    fn execute(self: Box<Self>, context: &mut Context) {
-        if !context.model.contains_key(&self.old) {
-            msg::no_such_key();
-            return;
-        }
-
-        if context.model.contains_key(&self.new) {
-            msg::failed_renaming();
-            return;
-        }
-
-        let value = context.model.remove(&self.old).unwrap();
-        context.model.insert(self.new, value);
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        context.rename(&self.old, self.new)
     }
 }
 
@@ -138,11 +159,12 @@ impl From<(String, String)> for Rename {
 pub struct Clear;
 
 impl Command for Clear {
-    fn execute(self: Box<Self>, context: &mut Context) {
-        match dialog::confirm(msg::strings::CLEAR) {
-            Ok(answer) => if answer { context.model.clear(); },
-            Err(_) => msg::input_failed(),
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        if context.confirm(msg::strings::CLEAR)? {
+            context.model.clear();
         }
+
+        Ok(())
     }
 }
 
@@ -151,14 +173,25 @@ pub struct Copy {
 }
 
 impl Command for Copy {
-    fn execute(self: Box<Self>, context: &mut Context) {
-        match context.model.get(&self.key) {
-            Some(value) => {
-                let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
-                ctx.set_contents(value.clone()).unwrap();
-            },
-            None => msg::no_such_key(),
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let entry = context.get(&self.key)?;
+
+        let mut ctx: ClipboardContext = ClipboardProvider::new().map_err(|_| InputError::Clipboard)?;
+        ctx.set_contents(entry.value.expose().to_owned()).map_err(|_| InputError::Clipboard)?;
+
+        let clear_after = context.config.clipboard.clear_after.unwrap_or(0);
+        if clear_after == 0 { return Ok(()) }
+
+        msg::clearing_clipboard(clear_after);
+        thread::sleep(Duration::from_secs(clear_after));
+
+        // Something copied in the meantime is left alone.
+        let contents = ctx.get_contents().ok().map(SecretString::from);
+        if contents.as_ref() == Some(&entry.value) {
+            ctx.set_contents(String::new()).map_err(|_| InputError::Clipboard)?;
         }
+
+        Ok(())
     }
 }
 
@@ -173,17 +206,14 @@ pub struct Paste {
 }
 
 impl Command for Paste {
-    fn execute(self: Box<Self>, context: &mut Context) {
-        match context.model.contains_key(&self.key) {
-            true => msg::already_exist(),
-            false => {
-                let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
-                match ctx.get_contents() {
-                    Ok(pass) => { context.model.insert(self.key, pass); },
-                    Err(_) => msg::pass_read_error(dialog::PassReadError::SystemError),
-                }
-            }
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        if context.model.contains_key(&self.key) {
+            return Err(Error::AlreadyExists(self.key));
         }
+
+        let mut ctx: ClipboardContext = ClipboardProvider::new().map_err(|_| InputError::Clipboard)?;
+        let pass = ctx.get_contents().map_err(|_| InputError::Clipboard)?;
+        context.add(self.key, SecretString::from(pass))
     }
 }
 
@@ -192,3 +222,14 @@ impl From<String> for Paste {
         Paste { key }
     }
 }
+
+fn new_password(context: &mut Context, generate: Option<Generate>) -> Result<SecretString, Error> {
+    match generate {
+        Some(Generate { length }) => {
+            let defaults = &context.config.generator;
+            let length = length.or(defaults.length).unwrap_or(generator::DEFAULT_LENGTH);
+            Ok(generator::generate(length, defaults.symbols.unwrap_or(true)))
+        },
+        None => context.prompt.password(true),
+    }
+}
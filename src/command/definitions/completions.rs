@@ -0,0 +1,281 @@
+use pass::context::Context;
+use pass::error::Error;
+
+use super::{
+    Command,
+    StoreAccess,
+    args::{self, Opt, Positional, Spec},
+};
+use crate::command::resolver::{CommandInfo, COMMANDS, GLOBAL_SPEC};
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+const PROGRAM: &str = "pass";
+/// Lists the keys for the completion of key arguments.
+const LIST_KEYS: &str = "pass list 2>/dev/null";
+/// Arguments completed with the keys of the store.
+const KEY_ARGUMENTS: &[&str] = &["<key>", "<old_key>"];
+
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Shell, ()> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(()),
+        }
+    }
+}
+
+pub struct Completions {
+    pub shell: Shell,
+}
+
+impl Command for Completions {
+    fn execute(self: Box<Self>, _context: &mut Context) -> Result<(), Error> {
+        let script = match self.shell {
+            Shell::Bash => bash(),
+            Shell::Zsh => zsh(),
+            Shell::Fish => fish(),
+        };
+
+        print!("{}", script);
+
+        Ok(())
+    }
+
+    fn store_access(&self) -> StoreAccess {
+        StoreAccess::Unused
+    }
+}
+
+fn bash() -> String {
+    let mut script = String::new();
+
+    let global_values = GLOBAL_SPEC.options.iter()
+        .filter(|o| o.value.is_some())
+        .map(|o| o.name)
+        .collect::<Vec<&str>>()
+        .join("|");
+
+    let mut first_words = option_names(&GLOBAL_SPEC);
+    first_words.extend(COMMANDS.iter().flat_map(names));
+
+    writeln!(script, "_pass() {{").unwrap();
+    writeln!(script, "    local cur=${{COMP_WORDS[COMP_CWORD]}} cmd=\"\" i").unwrap();
+    writeln!(script, "    for ((i = 1; i < COMP_CWORD; i++)); do").unwrap();
+    writeln!(script, "        case ${{COMP_WORDS[i]}} in").unwrap();
+    writeln!(script, "            {}) ((i++)) ;;", global_values).unwrap();
+    writeln!(script, "            -*) ;;").unwrap();
+    writeln!(script, "            *) cmd=${{COMP_WORDS[i]}}; break ;;").unwrap();
+    writeln!(script, "        esac").unwrap();
+    writeln!(script, "    done").unwrap();
+    writeln!(script, "    case $cmd in").unwrap();
+    writeln!(script, "        \"\") COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")) ;;", first_words.join(" ")).unwrap();
+
+    for command in COMMANDS {
+        let spec = command.builder.spec();
+        let mut words = option_names(&spec);
+        words.push(args::HELP_FLAG);
+        words.extend(positional_choices(&spec));
+        let keys = format!("$({})", LIST_KEYS);
+        if completes_keys(&spec) {
+            words.push(&keys);
+        }
+
+        let files = if completes_files(&spec) { " -f" } else { "" };
+
+        writeln!(script, "        {}) COMPREPLY=($(compgen{} -W \"{}\" -- \"$cur\")) ;;", names(command).join("|"), files, words.join(" ")).unwrap();
+    }
+
+    writeln!(script, "    esac").unwrap();
+    writeln!(script, "}}").unwrap();
+    writeln!(script, "complete -F _pass {}", PROGRAM).unwrap();
+
+    script
+}
+
+fn zsh() -> String {
+    let mut script = String::new();
+
+    writeln!(script, "#compdef {}", PROGRAM).unwrap();
+    writeln!(script).unwrap();
+    writeln!(script, "_pass_keys() {{").unwrap();
+    writeln!(script, "    local -a keys").unwrap();
+    writeln!(script, "    keys=(${{(f)\"$({})\"}})", LIST_KEYS).unwrap();
+    writeln!(script, "    _describe 'key' keys").unwrap();
+    writeln!(script, "}}").unwrap();
+    writeln!(script).unwrap();
+    writeln!(script, "_pass() {{").unwrap();
+    writeln!(script, "    local -a commands").unwrap();
+    writeln!(script, "    commands=(").unwrap();
+    for command in COMMANDS {
+        for name in names(command) {
+            writeln!(script, "        '{}:{}'", name, zsh_quote(&command.summary.replace(':', "\\:"))).unwrap();
+        }
+    }
+    writeln!(script, "    )").unwrap();
+    writeln!(script).unwrap();
+    writeln!(script, "    _arguments -C \\").unwrap();
+    for opt in GLOBAL_SPEC.options {
+        writeln!(script, "        {} \\", zsh_option(opt)).unwrap();
+    }
+    writeln!(script, "        '1: :->command' \\").unwrap();
+    writeln!(script, "        '*:: :->args'").unwrap();
+    writeln!(script).unwrap();
+    writeln!(script, "    case $state in").unwrap();
+    writeln!(script, "        command) _describe 'command' commands ;;").unwrap();
+    writeln!(script, "        args)").unwrap();
+    writeln!(script, "            case $words[1] in").unwrap();
+
+    for command in COMMANDS {
+        let spec = command.builder.spec();
+        let mut specs = spec.options.iter().map(zsh_option).collect::<Vec<String>>();
+        specs.push(format!("'{}[show the arguments]'", args::HELP_FLAG));
+        specs.extend(zsh_positionals(spec.positionals));
+
+        writeln!(script, "                {}) _arguments {} ;;", names(command).join("|"), specs.join(" ")).unwrap();
+    }
+
+    writeln!(script, "            esac ;;").unwrap();
+    writeln!(script, "    esac").unwrap();
+    writeln!(script, "}}").unwrap();
+    writeln!(script).unwrap();
+    writeln!(script, "_pass \"$@\"").unwrap();
+
+    script
+}
+
+fn fish() -> String {
+    let mut script = String::new();
+
+    writeln!(script, "complete -c {} -f", PROGRAM).unwrap();
+
+    for opt in GLOBAL_SPEC.options {
+        writeln!(script, "complete -c {} -n __fish_use_subcommand {}", PROGRAM, fish_option(opt)).unwrap();
+    }
+
+    for command in COMMANDS {
+        for name in names(command) {
+            writeln!(script, "complete -c {} -n __fish_use_subcommand -a {} -d '{}'", PROGRAM, name, fish_quote(command.summary)).unwrap();
+        }
+
+        let spec = command.builder.spec();
+        let condition = format!("-n '__fish_seen_subcommand_from {}'", names(command).join(" "));
+
+        for opt in spec.options {
+            writeln!(script, "complete -c {} {} {}", PROGRAM, condition, fish_option(opt)).unwrap();
+        }
+
+        if completes_keys(&spec) {
+            writeln!(script, "complete -c {} {} -a '({})'", PROGRAM, condition, LIST_KEYS).unwrap();
+        }
+
+        if completes_files(&spec) {
+            writeln!(script, "complete -c {} {} -F", PROGRAM, condition).unwrap();
+        }
+
+        let choices = positional_choices(&spec);
+        if !choices.is_empty() {
+            writeln!(script, "complete -c {} {} -a '{}'", PROGRAM, condition, choices.join(" ")).unwrap();
+        }
+    }
+
+    script
+}
+
+fn names(command: &CommandInfo) -> Vec<&'static str> {
+    std::iter::once(command.name).chain(command.aliases.iter().copied()).collect()
+}
+
+fn option_names(spec: &Spec) -> Vec<&'static str> {
+    spec.options.iter().map(|o| o.name).collect()
+}
+
+fn completes_keys(spec: &Spec) -> bool {
+    spec.positionals.iter().any(|p| KEY_ARGUMENTS.contains(&p.name))
+}
+
+fn positional_choices(spec: &Spec) -> Vec<&'static str> {
+    spec.positionals.iter().filter_map(|p| value_choices(p.name)).flatten().collect()
+}
+
+fn completes_files(spec: &Spec) -> bool {
+    spec.positionals.iter().any(|p| p.name.contains("path"))
+}
+
+/// Alternatives like "native|kdbx" are offered as they are, other values are taken for file names
+/// when they look like one.
+fn value_choices(value: &'static str) -> Option<Vec<&'static str>> {
+    match value.contains('|') {
+        true => Some(value.split('|').collect()),
+        false => None,
+    }
+}
+
+fn is_file_value(value: &str) -> bool {
+    value.contains("path") || value.contains("file")
+}
+
+fn zsh_option(opt: &Opt) -> String {
+    let help = zsh_quote(&opt.help.replace('[', "\\[").replace(']', "\\]"));
+
+    match opt.value {
+        None => format!("'{}[{}]'", opt.name, help),
+        Some(value) => {
+            let action = match value_choices(value) {
+                Some(choices) => format!("({})", choices.join(" ")),
+                None if is_file_value(value) => String::from("_files"),
+                None => String::new(),
+            };
+            format!("'{}[{}]:{}:{}'", opt.name, help, zsh_quote(value), action)
+        },
+    }
+}
+
+fn zsh_positionals(positionals: &[Positional]) -> Vec<String> {
+    positionals.iter()
+        .filter_map(|p| match (KEY_ARGUMENTS.contains(&p.name), p.name.contains("path"), value_choices(p.name)) {
+            (true, _, _) => Some(String::from("'*: :_pass_keys'")),
+            (_, true, _) => Some(String::from("'*: :_files'")),
+            (_, _, Some(choices)) => Some(format!("'*: :({})'", choices.join(" "))),
+            _ => None,
+        })
+        .take(1)
+        .collect()
+}
+
+fn zsh_quote(text: &str) -> String {
+    text.replace('\'', "'\\''")
+}
+
+fn fish_option(opt: &Opt) -> String {
+    let name = match opt.name.strip_prefix("--") {
+        Some(long) => format!("-l {}", long),
+        None => format!("-s {}", opt.name.trim_start_matches('-')),
+    };
+
+    let value = match opt.value {
+        None => String::new(),
+        Some(value) => match value_choices(value) {
+            Some(choices) => format!(" -x -a '{}'", choices.join(" ")),
+            None if is_file_value(value) => String::from(" -r -F"),
+            None => String::from(" -x"),
+        },
+    };
+
+    format!("{}{} -d '{}'", name, value, fish_quote(opt.help))
+}
+
+fn fish_quote(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
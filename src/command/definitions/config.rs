@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+
+use pass::context::Context;
+use pass::error::{Error, EXIT_NOT_FOUND};
+
+use super::{
+    Command,
+    StoreAccess,
+    args::OutputFormat,
+    tools::msg,
+};
+
+pub struct ConfigGet {
+    pub key: String,
+}
+
+impl Command for ConfigGet {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        // Like git config, an unset key is reported by the exit code only.
+        match context.config.get(&self.key) {
+            Some(value) => println!("{}", value),
+            None => context.exit_code = EXIT_NOT_FOUND,
+        }
+
+        Ok(())
+    }
+
+    fn store_access(&self) -> StoreAccess {
+        StoreAccess::Unused
+    }
+}
+
+pub struct ConfigSet {
+    pub key: String,
+    pub value: String,
+}
+
+impl Command for ConfigSet {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        context.config.set(&self.key, &self.value)?;
+        context.config.save()
+    }
+
+    fn store_access(&self) -> StoreAccess {
+        StoreAccess::Unused
+    }
+}
+
+pub struct ConfigUnset {
+    pub key: String,
+}
+
+impl Command for ConfigUnset {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        match context.config.unset(&self.key)? {
+            true => context.config.save(),
+            false => {
+                context.exit_code = EXIT_NOT_FOUND;
+                Ok(())
+            },
+        }
+    }
+
+    fn store_access(&self) -> StoreAccess {
+        StoreAccess::Unused
+    }
+}
+
+pub struct ConfigList {
+    pub output: OutputFormat,
+}
+
+impl Command for ConfigList {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let settings = context.config.settings();
+
+        match self.output {
+            OutputFormat::Text => {
+                msg::config_file(&context.config.path);
+                settings.iter().for_each(|(key, value)| println!("{} = {}", key, value));
+            },
+            OutputFormat::Json => msg::json(&settings.into_iter().collect::<BTreeMap<String, String>>()),
+        }
+
+        Ok(())
+    }
+
+    fn store_access(&self) -> StoreAccess {
+        StoreAccess::Unused
+    }
+}
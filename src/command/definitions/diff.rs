@@ -0,0 +1,162 @@
+use pass::config::Config;
+use pass::context::{self, Context, Entry, PassListModel};
+use pass::error::Error;
+use pass::merger::Base;
+use pass::tools::{changes, encryption, identity, signature, sync};
+
+use serde::Serialize;
+
+use super::{
+    Command,
+    args::OutputFormat,
+    impexp::{open_export, read_file},
+    tools::msg,
+};
+
+const STORE_NAME: &str = "the store";
+/// Exit code when the sides differ, the way diff does it.
+const EXIT_DIFFERENT: i32 = 1;
+
+/// Compares the store with an export, or two exports or stores, without changing anything.
+pub struct Diff {
+    pub path: String,
+    /// Compared with the first path instead of the store.
+    pub other_path: Option<String>,
+    pub key_file: Option<String>,
+    pub show_values: bool,
+    pub output: OutputFormat,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Side {
+    Left,
+    Right,
+    Both,
+}
+
+/// A key that differs, with the side that changed it when the last sync is known.
+#[derive(Serialize)]
+struct Difference<'a> {
+    key: &'a str,
+    #[serde(skip)]
+    left: Option<&'a Entry>,
+    #[serde(skip)]
+    right: Option<&'a Entry>,
+    in_left: bool,
+    in_right: bool,
+    changed_in: Option<Side>,
+}
+
+impl Command for Diff {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let (left_name, left, right_name, right, base) = match &self.other_path {
+            Some(other) => (self.path.clone(), load(&self.path, &self.key_file, context)?,
+                            other.clone(), load(other, &self.key_file, context)?, None),
+            None => (String::from(STORE_NAME), context.model.clone(),
+                     self.path.clone(), load(&self.path, &self.key_file, context)?,
+                     sync::load(&context.data_file_path, &self.path)?),
+        };
+
+        let differences = compare(&left, &right, base.as_ref());
+
+        match self.output {
+            OutputFormat::Text => differences.iter().for_each(|d| print_difference(d, &left_name, &right_name, self.show_values)),
+            OutputFormat::Json => msg::json(&differences),
+        }
+
+        if !differences.is_empty() {
+            context.exit_code = EXIT_DIFFERENT;
+        }
+
+        Ok(())
+    }
+
+    fn records_history(&self) -> bool {
+        false
+    }
+}
+
+/// Reads a store, or decrypts an export in memory.
+fn load(path: &str, key_file: &Option<String>, context: &mut Context) -> Result<PassListModel, Error> {
+    let data = read_file(path)?;
+
+    // Opening a missing store gives an empty one, the read above makes sure it's there.
+    match is_export(&data) {
+        true => open_export(&data, path, key_file, context, true),
+        false => Ok(Context::open(path, Config::default())?.model),
+    }
+}
+
+/// Exports start with one of their headers, or are binary files of earlier versions,
+/// while stores are text.
+fn is_export(data: &[u8]) -> bool {
+    signature::is_signed(data) || identity::is_age(data) || encryption::has_header(data)
+        || (!context::has_store_format(data) && std::str::from_utf8(data).is_err())
+}
+
+/// Prints how a store differs from the left one, false when they're the same.
+pub(super) fn preview(left_name: &str, left: &PassListModel, right_name: &str, right: &PassListModel, show_values: bool) -> bool {
+    let differences = compare(left, right, None);
+    differences.iter().for_each(|d| print_difference(d, left_name, right_name, show_values));
+    !differences.is_empty()
+}
+
+fn compare<'a>(left: &'a PassListModel, right: &'a PassListModel, base: Option<&Base>) -> Vec<Difference<'a>> {
+    let changes = changes::between(left, right);
+
+    let mut keys = changes.added.iter().chain(&changes.removed).chain(&changes.changed).collect::<Vec<&String>>();
+    keys.sort();
+
+    keys.into_iter().map(|key| {
+        let (key, l, r) = match (left.get_key_value(key), right.get_key_value(key)) {
+            (Some((k, l)), r) => (k, Some(l), r.map(|(_, r)| r)),
+            (None, Some((k, r))) => (k, None, Some(r)),
+            (None, None) => unreachable!("the key is on one of the sides"),
+        };
+
+        let changed_in = base.map(|base| match (base.matches(key, l), base.matches(key, r)) {
+            (true, _) => Side::Right,
+            (false, true) => Side::Left,
+            (false, false) => Side::Both,
+        });
+
+        Difference { key, left: l, right: r, in_left: l.is_some(), in_right: r.is_some(), changed_in }
+    }).collect()
+}
+
+fn print_difference(d: &Difference, left_name: &str, right_name: &str, show_values: bool) {
+    let marker = match (d.in_left, d.in_right) {
+        (true, false) => '-',
+        (false, true) => '+',
+        _ => '~',
+    };
+
+    let note = match d.changed_in {
+        None if !d.in_right => format!("only in {}", left_name),
+        None if !d.in_left => format!("only in {}", right_name),
+        None => String::from("different"),
+        Some(Side::Both) => String::from("changed on both sides"),
+        Some(side) => {
+            let (name, has, other_has) = match side {
+                Side::Left => (left_name, d.in_left, d.in_right),
+                _ => (right_name, d.in_right, d.in_left),
+            };
+            let verb = match (has, other_has) {
+                (true, false) => "added",
+                (false, true) => "removed",
+                _ => "changed",
+            };
+            format!("{} in {}", verb, name)
+        },
+    };
+
+    match show_values {
+        true => println!("{} {:24} {:28} {} -> {}", marker, d.key, note, value(d.left), value(d.right)),
+        false => println!("{} {:24} {}", marker, d.key, note),
+    }
+}
+
+fn value(entry: Option<&Entry>) -> &str {
+    entry.map(|e| e.value.expose()).unwrap_or("-")
+}
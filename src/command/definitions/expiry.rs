@@ -0,0 +1,98 @@
+use std::convert::TryFrom;
+
+use pass::context::{Context, SECONDS_IN_DAY};
+use pass::error::Error;
+
+use super::{
+    Command,
+    args::OutputFormat,
+    tools::msg,
+};
+
+pub const DEFAULT_EXPIRING_DAYS: u64 = 14;
+
+pub struct Expiring {
+    pub days: u64,
+    pub output: OutputFormat,
+}
+
+impl Command for Expiring {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let within = i64::try_from(self.days.saturating_mul(SECONDS_IN_DAY)).unwrap_or(i64::MAX);
+        let due = due_entries(context, within);
+
+        if due.is_empty() && self.output == OutputFormat::Text {
+            msg::nothing_expiring(self.days);
+            return Ok(());
+        }
+
+        print_due(&due, self.output);
+
+        Ok(())
+    }
+}
+
+pub struct MaxAge {
+    /// None sets the vault-wide default.
+    pub key: Option<String>,
+    pub days: Option<u64>,
+}
+
+impl Command for MaxAge {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        match self.key {
+            Some(key) => match context.model.get_mut(&key) {
+                Some(entry) => entry.max_age = self.days,
+                None => return Err(Error::NotFound(key)),
+            },
+            None => context.settings.max_age = self.days,
+        }
+
+        Ok(())
+    }
+}
+
+/// Entries that are due for rotation within the given number of seconds,
+/// ordered by due date. Entries with unknown modification time come first.
+pub fn due_entries(context: &Context, within: i64) -> Vec<(&String, Option<i64>)> {
+    let mut due = context.model.iter()
+        .filter_map(|(key, entry)| {
+            let expires_in = entry.expires_in(context.settings.max_age)?;
+            match entry.modified {
+                0 => Some((key, None)),
+                _ if expires_in <= within => Some((key, Some(expires_in))),
+                _ => None,
+            }
+        })
+        .collect::<Vec<(&String, Option<i64>)>>();
+
+    due.sort_by(|(f_key, f_due), (s_key, s_due)| f_due.cmp(s_due).then(f_key.cmp(s_key)));
+    due
+}
+
+/// Seconds until due are null for entries never rotated since tracking started.
+pub fn print_due(due: &[(&String, Option<i64>)], output: OutputFormat) {
+    match output {
+        OutputFormat::Text => due.iter().for_each(|(key, expires_in)| println!("{:20} {}", key, describe(*expires_in))),
+        OutputFormat::Json => msg::json(&due.iter()
+            .map(|(key, expires_in)| serde_json::json!({ "key": key, "due_in_seconds": expires_in }))
+            .collect::<Vec<serde_json::Value>>()),
+    }
+}
+
+pub fn describe(expires_in: Option<i64>) -> String {
+    let seconds = match expires_in {
+        Some(s) => s,
+        None => return String::from("never rotated since tracking started"),
+    };
+
+    let days = seconds.unsigned_abs() / SECONDS_IN_DAY;
+    let plural = if days == 1 { "" } else { "s" };
+
+    match (seconds < 0, days) {
+        (true, 0) => String::from("overdue since today"),
+        (true, _) => format!("overdue by {} day{}", days, plural),
+        (false, 0) => String::from("due today"),
+        (false, _) => format!("due in {} day{}", days, plural),
+    }
+}
@@ -0,0 +1,193 @@
+use pass::context::Context;
+use pass::error::Error;
+use pass::merger::Base;
+use pass::tools::history::History;
+
+use std::fs;
+
+use zeroize::Zeroizing;
+
+use super::{
+    Command,
+    tools::msg,
+};
+
+const NO_HISTORY: &str = "the store has no history, create it with \"pass git init\"";
+
+pub struct GitInit {
+    pub remote: Option<String>,
+    /// Sync key of the history on another machine.
+    pub key_file: Option<String>,
+}
+
+impl Command for GitInit {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let key = match &self.key_file {
+            Some(path) => Some(Zeroizing::new(fs::read(path).map_err(|err| Error::read(path, err))?)),
+            None => None,
+        };
+
+        let dir = History::dir(&context.data_file_path, &context.config);
+        let has_remote = self.remote.is_some();
+
+        let history = History::init(&dir, self.remote.as_deref(), key, |history| {
+            let pulled = match has_remote {
+                true => pull(context, history)?,
+                false => Pulled::UpToDate,
+            };
+
+            match pulled {
+                Pulled::UpToDate => history.commit(context, "Start the history"),
+                _ => Ok(()),
+            }
+        })?;
+
+        msg::history_created(&dir, &history.key_path());
+
+        Ok(())
+    }
+
+    fn records_history(&self) -> bool {
+        false
+    }
+}
+
+pub struct GitPush;
+
+impl Command for GitPush {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        open(context)?.push()
+    }
+
+    fn records_history(&self) -> bool {
+        false
+    }
+}
+
+pub struct GitPull;
+
+impl Command for GitPull {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let history = open(context)?;
+
+        if let Pulled::UpToDate = pull(context, &history)? {
+            msg::up_to_date();
+        }
+
+        Ok(())
+    }
+
+    fn records_history(&self) -> bool {
+        false
+    }
+}
+
+pub struct GitLog;
+
+impl Command for GitLog {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        print!("{}", open(context)?.log()?);
+
+        Ok(())
+    }
+
+    fn records_history(&self) -> bool {
+        false
+    }
+}
+
+enum Pulled {
+    UpToDate,
+    Merged,
+    Cancelled,
+}
+
+fn open(context: &Context) -> Result<History, Error> {
+    History::open(&context.data_file_path, &context.config)?
+        .ok_or_else(|| Error::Git(String::from(NO_HISTORY)))
+}
+
+/// Merges the remote store into ours with the usual conflict resolution, never textually,
+/// and records the result. Against the last common commit, removals are merged too.
+/// The store settings of the remote are taken unless ours changed since.
+fn pull(context: &mut Context, history: &History) -> Result<Pulled, Error> {
+    let remote = match history.fetch()? {
+        Some(remote) => remote,
+        None => return Ok(Pulled::UpToDate),
+    };
+
+    // Ours are kept when changed since the base, or set while there's none.
+    let settings_changed = match &remote.base {
+        Some((_, base_settings)) => context.settings.max_age != base_settings.max_age,
+        None => context.settings.max_age.is_some(),
+    };
+
+    let summary = match &remote.base {
+        Some((base, _)) => context.merge_with_base(remote.store, &Base::of(base))?,
+        None => context.merge(remote.store)?,
+    };
+
+    if summary.is_none() { return Ok(Pulled::Cancelled) }
+
+    if !settings_changed {
+        context.settings = remote.settings;
+    }
+
+    history.commit_merge(context)?;
+    Ok(Pulled::Merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use pass::config::Config;
+    use pass::context::Entry;
+    use pass::secret::SecretString;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pass-git-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn context(entries: &[(&str, &str)], max_age: Option<u64>) -> Context {
+        let mut context = Context::unopened("store", Config::default());
+        for &(key, value) in entries {
+            context.model.insert(String::from(key), Entry::new(SecretString::from(value)));
+        }
+        context.settings.max_age = max_age;
+        context
+    }
+
+    fn clone(dir: &Path, remote: &History, remote_dir: &Path, context: &mut Context) -> History {
+        let key = Zeroizing::new(fs::read(remote.key_path()).unwrap());
+        History::init(dir, Some(&remote_dir.to_string_lossy()), Some(key), |history| pull(context, history).map(|_| ())).unwrap()
+    }
+
+    #[test]
+    fn pull_takes_settings() {
+        let (first_dir, second_dir) = (temp_dir("settings-first"), temp_dir("settings-second"));
+        let mut first = context(&[("mail", "secret")], Some(30));
+        let first_history = History::init(&first_dir, None, None, |history| history.commit(&first, "Start")).unwrap();
+
+        let mut second = context(&[], None);
+        let second_history = clone(&second_dir, &first_history, &first_dir, &mut second);
+        assert_eq!(second.settings.max_age, Some(30));
+        assert!(second.model.contains_key("mail"));
+
+        // Changed here since, ours stay.
+        second.settings.max_age = Some(60);
+        first.model.insert(String::from("bank"), Entry::new(SecretString::from("other")));
+        first_history.commit(&first, "Add bank").unwrap();
+
+        pull(&mut second, &second_history).unwrap();
+        assert_eq!(second.settings.max_age, Some(60));
+        assert!(second.model.contains_key("bank"));
+
+        let _ = fs::remove_dir_all(&first_dir);
+        let _ = fs::remove_dir_all(&second_dir);
+    }
+}
@@ -0,0 +1,67 @@
+use pass::context::Context;
+use pass::error::{Error, InputError};
+use pass::tools::{identity::Identity, signature::SigningIdentity};
+
+use super::{
+    Command,
+    StoreAccess,
+    tools::msg,
+};
+
+/// Generates the key pair of the user and the key they sign exports with,
+/// or only the signing key when an identity of earlier versions exists.
+pub struct IdentityCreate;
+
+impl Command for IdentityCreate {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let path = context.config.identity_path();
+        let signing_key_path = context.config.signing_key_path();
+
+        let (identity, signing) = match (Identity::load(&path)?, SigningIdentity::load(&signing_key_path)?) {
+            (Some(_), Some(_)) => return Err(Error::AlreadyExists(path.to_string_lossy().into_owned())),
+            (identity, signing) => (
+                match identity { Some(identity) => identity, None => Identity::create(&path)? },
+                match signing { Some(signing) => signing, None => SigningIdentity::create(&signing_key_path)? },
+            ),
+        };
+
+        msg::identity_created(&path, &identity.public_key(), &signing.public_key());
+
+        Ok(())
+    }
+
+    fn records_history(&self) -> bool {
+        false
+    }
+
+    fn store_access(&self) -> StoreAccess {
+        StoreAccess::Unused
+    }
+}
+
+/// Prints a public key, for others to export to or to trust.
+pub struct IdentityShow {
+    /// The key exports are signed with, instead of the one they're sealed to.
+    pub signing: bool,
+}
+
+impl Command for IdentityShow {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let public_key = match self.signing {
+            true => SigningIdentity::load(&context.config.signing_key_path())?.map(|s| s.public_key()),
+            false => Identity::load(&context.config.identity_path())?.map(|i| i.public_key()),
+        };
+
+        println!("{}", public_key.ok_or(Error::Input(InputError::NoIdentity))?);
+
+        Ok(())
+    }
+
+    fn records_history(&self) -> bool {
+        false
+    }
+
+    fn store_access(&self) -> StoreAccess {
+        StoreAccess::Unused
+    }
+}
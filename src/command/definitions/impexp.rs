@@ -1,151 +1,502 @@
-use crate::context::{self, Context};
+use pass::context::{self, Context, PassListModel};
+use pass::config::SignaturePolicy;
+use pass::error::{CryptoError, Error, InputError, SignatureError};
+use pass::merger::{Base, Strategy};
+use pass::tools::{identity::{self, Identity, Recipients}, signature::{self, SigningIdentity}, sync};
 
 use super::{
     Command,
-    tools::{msg, merger, encryption, dialog},
+    StoreAccess,
+    tools::{msg, encryption::{self, EncryptionOptions}},
 };
 
-use std::fs::File;
-use std::io::{Read, Write};
+use std::fs::{self, OpenOptions};
+use std::path::Path;
+use std::io::{ErrorKind, Write};
+
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+pub enum ExportFormat {
+    Native,
+    Kdbx,
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Result<ExportFormat, ()> {
+        match name {
+            FORMAT_NATIVE => Ok(ExportFormat::Native),
+            FORMAT_KDBX => Ok(ExportFormat::Kdbx),
+            _ => Err(()),
+        }
+    }
+}
 
 pub struct Export {
     pub dest: String,
     pub clear: bool,
+    pub format: ExportFormat,
+    pub key_file: Option<String>,
+    pub encryption: EncryptionOptions,
+    pub selection: Selection,
+    /// Public keys or recipient files to seal the export to, instead of a passphrase.
+    pub to: Vec<String>,
+    pub sign: bool,
 }
 
+/// The entries to export, all of them when nothing is given.
+/// An entry is taken when it matches any of the criteria.
+#[derive(Default)]
+pub struct Selection {
+    /// Keys, or patterns where "*" stands for any text and "?" for any character.
+    pub keys: Vec<String>,
+    pub prefix: Option<String>,
+    pub tag: Option<String>,
+}
+
+impl Selection {
+    fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.prefix.is_none() && self.tag.is_none()
+    }
+
+    /// The selected keys in alphabetical order. A key given without a pattern must exist.
+    fn select(&self, context: &Context) -> Result<Vec<String>, Error> {
+        if let Some(key) = self.keys.iter().find(|k| !is_pattern(k) && !context.model.contains_key(*k)) {
+            return Err(Error::NotFound(key.clone()));
+        }
+
+        let keys = context.keys().into_iter()
+            .filter(|key| self.keys.iter().any(|pattern| glob_matches(pattern, key))
+                || self.prefix.as_ref().is_some_and(|prefix| key.starts_with(prefix))
+                || self.tag.as_ref().is_some_and(|tag| context.model[*key].has_tag(tag)))
+            .cloned()
+            .collect::<Vec<String>>();
+
+        match keys.is_empty() {
+            true => Err(Error::NotFound(self.describe())),
+            false => Ok(keys),
+        }
+    }
+
+    fn describe(&self) -> String {
+        let prefix = self.prefix.as_ref().map(|p| format!("{}*", p));
+        let tag = self.tag.as_ref().map(|t| format!("tag {}", t));
+
+        self.keys.iter().cloned().chain(prefix).chain(tag).collect::<Vec<String>>().join(", ")
+    }
+}
+
+pub const FORMAT_NATIVE: &str = "native";
+pub const FORMAT_KDBX: &str = "kdbx";
+
 const DEFAULT_IMPORT_EXPORT_FILENAME: &str = "data_exported";
+const KDBX_EXTENSION: &str = ".kdbx";
+const KEY_FILE_LEN: usize = 64;
+const EXPORT_FILE: &str = "export file";
 
 impl Command for Export {
-    fn execute(self: Box<Self>, context: &mut Context) {
-        let mut file = File::open(&context.data_file_path).unwrap();
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let keys = match self.selection.is_empty() {
+            true => None,
+            false => Some(self.selection.select(context)?),
+        };
 
-        let mut data = vec![];
-        file.read_to_end(&mut data).unwrap();
+        let recipients = match self.to.is_empty() {
+            false => Some(Recipients::parse(&self.to)?),
+            true => None,
+        };
 
-        let pass = match dialog::ask_for_password(true) {
-            Ok(p) => p,
-            Err(err) => {
-                msg::pass_read_error(err);
-                return;
+        // Checked before anything is written, the cleared entries would be lost for us.
+        if let (true, Some(recipients)) = (self.clear, &recipients) {
+            let identity = Identity::load(&context.config.identity_path())?;
+            if !identity.is_some_and(|identity| recipients.includes(&identity)) {
+                return Err(Error::Input(InputError::ClearForOthers));
             }
-        };
+        }
+
+        let result = match &recipients {
+            Some(recipients) => context.export_to(keys.as_deref(), recipients)?,
+            None => {
+                let key_file = load_key_file(&self.key_file)?;
+                let pass = context.prompt.password(true)?;
+
+                let params = self.encryption.resolve(&context.config.kdf);
 
-        let result = match encryption::encrypt(&data, &pass) {
-            Ok(d) => d,
-            Err(_) => {
-                msg::encryption_failed();
-                return;
+                let key_file = key_file.as_deref().map(|k| &k[..]);
+
+                match self.format {
+                    ExportFormat::Native => context.export(keys.as_deref(), pass.expose(), key_file, params)?,
+                    ExportFormat::Kdbx => {
+                        let (data, renamed) = context.export_kdbx(keys.as_deref(), pass.expose(), key_file, params.kdf)?;
+                        renamed.iter().for_each(|key| msg::kdbx_title_collision(key));
+                        data
+                    },
+                }
             },
         };
 
-        let dest_path = if self.dest.len() > 0 { self.dest } else { make_default_path() };
+        let result = match self.sign {
+            true => signing_identity(context)?.sign(&result),
+            false => result,
+        };
 
-        match File::create(&dest_path) {
-            Ok(mut f) => {
-                f.write(&result).unwrap();
-                if self.clear { context.model.clear() }
-            },
-            Err(_) => {
-                msg::failed_writing(&dest_path);
-                return;
+        let dest_path = match (!self.dest.is_empty(), &self.format) {
+            (true, _) => self.dest,
+            (false, ExportFormat::Native) => default_export_path(context),
+            (false, ExportFormat::Kdbx) => default_export_path(context) + KDBX_EXTENSION,
+        };
+
+        context::write_atomically(&dest_path, &result).map_err(|err| Error::write(&dest_path, err))?;
+
+        // A cleared store isn't the removal of everything, the export is its only copy.
+        if let ExportFormat::Native = self.format {
+            match self.clear {
+                true => sync::forget(&context.data_file_path, &dest_path)?,
+                false => {
+                    let exported = match &keys {
+                        Some(keys) => Base::of(&context.selected(keys)),
+                        None => Base::of(&context.model),
+                    };
+                    sync::save(&context.data_file_path, &dest_path, &exported)?
+                },
             }
         }
-    }
-}
 
-impl From::<(String, bool)> for Export {
-    fn from((dest, clear): (String, bool)) -> Export {
-        Export { dest, clear }
+        if self.clear {
+            match keys {
+                Some(keys) => keys.iter().for_each(|key| { context.model.remove(key); }),
+                None => context.model.clear(),
+            }
+        }
+
+        Ok(())
     }
 }
 
 pub struct Import {
     pub src: String,
     pub clear: bool,
+    pub key_file: Option<String>,
+    /// Resolves the conflicts instead of asking.
+    pub strategy: Option<Strategy>,
 }
 
 impl Command for Import {
-    fn execute(self: Box<Self>, context: &mut Context) {
-        let src_path = if self.src.len() > 0 { self.src } else { make_default_path() };
-
-        let data = match read_file(&src_path) {
-            Ok(d) => d,
-            Err(_) => {
-                msg::failed_reading(&src_path);
-                return;
-            },
-        };
-
-        let pass = match dialog::ask_for_password(false) {
-            Ok(p) => p,
-            Err(err) => {
-                msg::pass_read_error(err);
-                return;
-            }
-        };
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let src_path = if !self.src.is_empty() { self.src } else { default_export_path(context) };
 
-        let str_model = match encryption::decrypt(&data, &pass) {
-            Ok(bytes) => match String::from_utf8(bytes) {
-                Ok(s) => s,
-                Err(_) => {
-                    msg::bad_file();
-                    return;
-                },
-            },
-            Err(_) => {
-                msg::decryption_failed();
-                return;
-            },
-        };
+        let data = read_file(&src_path)?;
+        let imported_model = open_export(&data, &src_path, &self.key_file, context, false)?;
+        let imported = Base::of(&imported_model);
 
-        let imported_model = match context::model_from_string(str_model) {
-            Ok(m) => m,
-            Err(_) => {
-                msg::bad_file();
-                return;
-            },
-        };
+        if let Some(strategy) = self.strategy {
+            context.resolver = Box::new(strategy);
+        }
 
         if self.clear {
             context.model = imported_model;
-            return;
+        } else {
+            let summary = match sync::load(&context.data_file_path, &src_path)? {
+                Some(base) => context.merge_with_base(imported_model, &base)?,
+                None => context.merge(imported_model)?,
+            };
+
+            match summary {
+                Some(summary) => msg::merge_summary(&summary),
+                None => return Ok(()),
+            }
         }
 
-        let collisions = context::find_collisions(&imported_model, &mut context.model);
+        // The next import only takes over what changed in the file from now on.
+        sync::save(&context.data_file_path, &src_path, &imported)?;
 
-        match collisions.len() > 0 {
-            true => {
-                msg::collision_detected();
-                collisions.iter().for_each(|c| println!("{}", c));
-                merger::interactive_merge(imported_model, &mut context.model);
-            },
-            false => context::merge_models(imported_model, &mut context.model),
+        Ok(())
+    }
+}
+
+pub struct KeyGen {
+    pub path: String,
+}
+
+impl Command for KeyGen {
+    fn execute(self: Box<Self>, _context: &mut Context) -> Result<(), Error> {
+        let mut key = Zeroizing::new(vec![0; KEY_FILE_LEN]);
+        rand::thread_rng().fill_bytes(&mut key);
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        // The key file is a second factor, only its owner may read it.
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        match options.open(&self.path) {
+            Ok(mut f) => f.write_all(&key).map_err(|err| Error::write(&self.path, err)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => Err(Error::AlreadyExists(self.path)),
+            Err(err) => Err(Error::write(&self.path, err)),
         }
     }
+
+    fn store_access(&self) -> StoreAccess {
+        StoreAccess::Unused
+    }
 }
 
-impl From::<(String, bool)> for Import {
-    fn from((src, clear): (String, bool)) -> Import {
-        Import { src, clear }
+impl From<String> for KeyGen {
+    fn from(path: String) -> KeyGen {
+        KeyGen { path }
     }
 }
 
-fn read_file(path: &str) -> Result<Vec<u8>, ()> {
-    match File::open(path) {
-        Ok(mut f) => {
-            let mut data = vec![];
-            match f.read_to_end(&mut data) {
-                Ok(_) => Ok(data),
-                Err(_) => Err(()),
-            }
-        },
-        Err(_) => return Err(()),
+pub struct Rekey {
+    pub path: String,
+    pub key_file: Option<String>,
+    pub new_key_file: NewKeyFile,
+    pub encryption: EncryptionOptions,
+}
+
+/// The key file protecting a rekeyed export.
+pub enum NewKeyFile {
+    /// The one it's protected with now, if any.
+    Same,
+    Path(String),
+    /// Only the passphrase from now on.
+    None,
+}
+
+impl Command for Rekey {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let path = if !self.path.is_empty() { self.path } else { default_export_path(context) };
+
+        let data = read_file(&path)?;
+        if identity::is_age(&data) {
+            return Err(Error::Crypto(CryptoError::Sealed));
+        }
+        if signature::is_signed(&data) {
+            return Err(Error::Signature(SignatureError::Signed));
+        }
+        check_key_file(&data, &path, &self.key_file)?;
+
+        let key_file = load_key_file(&self.key_file)?;
+        let new_key_file = match self.new_key_file {
+            NewKeyFile::Same => key_file.clone(),
+            NewKeyFile::Path(new_path) => load_key_file(&Some(new_path))?,
+            NewKeyFile::None => None,
+        };
+
+        msg::enter_current_password();
+        let pass = context.prompt.password(false)?;
+
+        let decrypted = encryption::decrypt(&data, pass.expose(), key_file.as_deref().map(|k| &k[..]))?;
+
+        msg::enter_new_password();
+        let new_pass = context.prompt.password(true)?;
+
+        let params = self.encryption.resolve(&context.config.kdf);
+
+        let encrypted = encryption::encrypt(&decrypted, new_pass.expose(), new_key_file.as_deref().map(|k| &k[..]), params)?;
+
+        context::write_atomically(&path, &encrypted).map_err(|err| Error::write(&path, err))
     }
 }
 
-fn make_default_path() -> String {
+/// Opens an export with our identity when it was sealed to public keys, and with the passphrase
+/// and key file otherwise. Announced, the path is shown when asking for the passphrase.
+pub(super) fn open_export(data: &[u8], path: &str, key_file: &Option<String>, context: &mut Context, announce: bool) -> Result<PassListModel, Error> {
+    let data = check_signature(data, context)?;
+
+    if identity::is_age(data) {
+        let identity = Identity::load(&context.config.identity_path())?
+            .ok_or(Error::Input(InputError::NoIdentity))?;
+
+        return context::read_sealed_export(data, path, &identity);
+    }
+
+    check_key_file(data, path, key_file)?;
+    let key_file = load_key_file(key_file)?;
+
+    if announce {
+        msg::enter_password_of(Path::new(path));
+    }
+    let pass = context.prompt.password(false)?;
+
+    context::read_export(data, path, pass.expose(), key_file.as_deref().map(|k| &k[..]))
+}
+
+/// The export without its signature. Who signed it is shown, and the config may refuse
+/// unsigned exports or unknown signers.
+fn check_signature<'a>(data: &'a [u8], context: &Context) -> Result<&'a [u8], Error> {
+    let policy = context.config.import.signatures.unwrap_or(SignaturePolicy::Any);
+
+    let signed = match signature::verify(data)? {
+        Some(signed) => signed,
+        None if policy == SignaturePolicy::Any => return Ok(data),
+        None => return Err(Error::Signature(SignatureError::Missing)),
+    };
+
+    match context.config.signers.iter().find(|(_, key)| **key == signed.signer) {
+        Some((name, _)) => msg::signed_by(name, true),
+        None if policy == SignaturePolicy::Trusted => return Err(Error::Signature(SignatureError::Untrusted(signed.signer))),
+        None => msg::signed_by(&signed.signer, false),
+    }
+
+    Ok(signed.export)
+}
+
+fn signing_identity(context: &Context) -> Result<SigningIdentity, Error> {
+    SigningIdentity::load(&context.config.signing_key_path())?
+        .ok_or(Error::Input(InputError::NoIdentity))
+}
+
+/// Fails early when the file needs a key file that wasn't given, before asking for the passphrase.
+fn check_key_file(data: &[u8], path: &str, key_file: &Option<String>) -> Result<(), Error> {
+    let header = encryption::read_header(data).map_err(|_| Error::parse(path, EXPORT_FILE))?;
+
+    match header.key_file_required && key_file.is_none() {
+        true => Err(Error::Input(InputError::KeyFileRequired)),
+        false => Ok(()),
+    }
+}
+
+fn load_key_file(path: &Option<String>) -> Result<Option<Zeroizing<Vec<u8>>>, Error> {
+    match path {
+        Some(p) => Ok(Some(Zeroizing::new(read_file(p)?))),
+        None => Ok(None),
+    }
+}
+
+pub(super) fn read_file(path: &str) -> Result<Vec<u8>, Error> {
+    fs::read(path).map_err(|err| Error::read(path, err))
+}
+
+fn is_pattern(key: &str) -> bool {
+    key.contains(['*', '?'])
+}
+
+/// Whether the whole text matches the pattern. On a mismatch after "*",
+/// the star is retried taking one more character.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.chars().collect::<Vec<char>>(), text.chars().collect::<Vec<char>>());
+    let (mut p, mut t) = (0, 0);
+    let mut star = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            },
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            },
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                },
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// The export path of the config, or a file next to the program.
+fn default_export_path(context: &Context) -> String {
+    if let Some(path) = &context.config.store.export_path {
+        return path.clone();
+    }
+
     let mut dir = std::env::current_exe().unwrap();
     dir.pop();
     dir.push(DEFAULT_IMPORT_EXPORT_FILENAME);
     String::from(dir.to_str().unwrap())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use pass::config::Config;
+    use pass::context::Entry;
+    use pass::secret::SecretString;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pass-impexp-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn context(dir: &Path) -> Context {
+        let config = Config { path: dir.join("config.toml").to_string_lossy().into_owned(), ..Config::default() };
+        let mut context = Context::unopened(&dir.join("store").to_string_lossy(), config);
+        context.model.insert(String::from("mail"), Entry::new(SecretString::from("secret")));
+        context
+    }
+
+    fn sealed_export(dest: &Path, to: Vec<String>) -> Box<Export> {
+        Box::new(Export {
+            dest: dest.to_string_lossy().into_owned(),
+            clear: true,
+            format: ExportFormat::Native,
+            key_file: None,
+            encryption: EncryptionOptions::default(),
+            selection: Selection::default(),
+            to,
+            sign: false,
+        })
+    }
+
+    #[test]
+    fn clear_sealed_to_others() {
+        let dir = temp_dir("clear-others");
+        let mut context = context(&dir);
+        Identity::create(&context.config.identity_path()).unwrap();
+        let teammate = Identity::create(&dir.join("teammate.txt")).unwrap().public_key();
+
+        let dest = dir.join("export.age");
+        let result = sealed_export(&dest, vec![teammate]).execute(&mut context);
+
+        assert!(matches!(result, Err(Error::Input(InputError::ClearForOthers))));
+        assert!(!dest.exists());
+        assert!(context.model.contains_key("mail"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn clear_without_identity() {
+        let dir = temp_dir("clear-no-identity");
+        let mut context = context(&dir);
+        let teammate = Identity::create(&dir.join("teammate.txt")).unwrap().public_key();
+
+        let result = sealed_export(&dir.join("export.age"), vec![teammate]).execute(&mut context);
+
+        assert!(matches!(result, Err(Error::Input(InputError::ClearForOthers))));
+        assert!(context.model.contains_key("mail"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn clear_sealed_to_us() {
+        let dir = temp_dir("clear-us");
+        let mut context = context(&dir);
+        let identity = Identity::create(&context.config.identity_path()).unwrap();
+        let teammate = Identity::create(&dir.join("teammate.txt")).unwrap().public_key();
+
+        let dest = dir.join("export.age");
+        sealed_export(&dest, vec![teammate, identity.public_key()]).execute(&mut context).unwrap();
+
+        assert!(context.model.is_empty());
+        let opened = context::read_sealed_export(&fs::read(&dest).unwrap(), "export", &identity).unwrap();
+        assert_eq!(opened["mail"].value.expose(), "secret");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
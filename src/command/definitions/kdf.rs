@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use pass::context::Context;
+use pass::error::Error;
+
+use super::{
+    Command,
+    StoreAccess,
+    args::OutputFormat,
+    tools::{msg, calibration, encryption::{EncryptionOptions, KdfParams}},
+};
+
+pub const DEFAULT_CALIBRATION_TARGET: &str = "1s";
+
+pub struct KdfCalibrate {
+    pub target: Duration,
+}
+
+impl Command for KdfCalibrate {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        msg::calibrating(self.target);
+
+        let argon2 = calibration::calibrate_argon2(self.target)?;
+        let pbkdf2 = calibration::calibrate_pbkdf2(self.target)?;
+
+        let kdf = &mut context.config.kdf;
+        kdf.memory_kib = Some(argon2.memory_kib);
+        kdf.iterations = Some(argon2.iterations);
+        kdf.parallelism = Some(argon2.parallelism);
+        kdf.pbkdf2_iterations = Some(pbkdf2);
+
+        context.config.save()?;
+
+        print_params(argon2, pbkdf2);
+
+        Ok(())
+    }
+
+    fn store_access(&self) -> StoreAccess {
+        StoreAccess::Unused
+    }
+}
+
+pub struct KdfShow {
+    pub output: OutputFormat,
+}
+
+impl Command for KdfShow {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let params = EncryptionOptions::default().resolve(&context.config.kdf);
+
+        match self.output {
+            OutputFormat::Text => print_params(params.kdf, params.pbkdf2_iterations),
+            OutputFormat::Json => msg::json(&serde_json::json!({
+                "memory_kib": params.kdf.memory_kib,
+                "iterations": params.kdf.iterations,
+                "parallelism": params.kdf.parallelism,
+                "pbkdf2_iterations": params.pbkdf2_iterations,
+            })),
+        }
+
+        Ok(())
+    }
+
+    fn store_access(&self) -> StoreAccess {
+        StoreAccess::Unused
+    }
+}
+
+fn print_params(argon2: KdfParams, pbkdf2_iterations: u32) {
+    let unlock = match calibration::measure_argon2(argon2) {
+        Ok(d) => format!("{:.2}s", d.as_secs_f64()),
+        Err(_) => String::from("unknown"),
+    };
+
+    println!("Argon2id: {} MiB, {} iterations, {} lanes (unlocks in {})",
+        argon2.memory_kib / 1024, argon2.iterations, argon2.parallelism, unlock);
+    println!("PBKDF2-SHA256 (legacy format): {} iterations", pbkdf2_iterations);
+}
@@ -1,8 +1,9 @@
-use crate::context::{self, Context, PassListModel};
+use pass::context::{Context, Entry, PassListModel};
+use pass::error::Error;
 
 use super::{
     Command,
-    tools::{msg, dialog, merger},
+    tools::msg,
 };
 
 pub struct MultiAdd {
@@ -10,29 +11,14 @@ pub struct MultiAdd {
 }
 
 impl Command for MultiAdd {
-    fn execute(self: Box<Self>, context: &mut Context) {
-        let pass = match dialog::ask_for_password(true) {
-            Ok(p) => p,
-            Err(err) => {
-                msg::pass_read_error(err);
-                return;
-            },
-        };
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let pass = context.prompt.password(true)?;
 
         let mut extension = PassListModel::new();
 
-        self.keys.into_iter().for_each(|k| { extension.insert(k, pass.clone()); });
+        self.keys.into_iter().for_each(|k| { extension.insert(k, Entry::new(pass.clone())); });
 
-        let collisions = context::find_collisions(&extension, &mut context.model);
-
-        match collisions.len() > 0 {
-            true => {
-                msg::collision_detected();
-                collisions.iter().for_each(|c| println!("{}", c));
-                merger::interactive_merge(extension, &mut context.model);
-            },
-            false => context::merge_models(extension, &mut context.model),
-        }
+        context.merge(extension).map(|_| ())
     }
 }
 
@@ -47,18 +33,18 @@ pub struct MultiRemove {
 }
 
 impl Command for MultiRemove {
-    fn execute(self: Box<Self>, context: &mut Context) {
-        match dialog::confirm(msg::strings::MRM) {
-            Ok(true) => self.keys.iter().for_each(|key| {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        if context.confirm(msg::strings::MRM)? {
+            self.keys.iter().for_each(|key| {
                 if !context.model.contains_key(key) {
                     msg::no_such_key_warning(key);
                     return;
                 }
                 context.model.remove(key);
-            }),
-            Err(_) => msg::input_failed(),
-            _ => (),
+            });
         }
+
+        Ok(())
     }
 }
 
@@ -67,23 +53,15 @@ pub struct MultiUpdate {
 }
 
 impl Command for MultiUpdate {
-    fn execute(self: Box<Self>, context: &mut Context) {
-        let pass = match dialog::ask_for_password(true) {
-            Ok(p) => p,
-            Err(err) => {
-                msg::pass_read_error(err);
-                return;
-            },
-        };
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let pass = context.prompt.password(true)?;
 
-        self.keys.into_iter().for_each(|key| {
-            if !context.model.contains_key(&key) {
-                msg::no_such_key_warning(&key);
-                return;
-            }
-
-            context.model.insert(key, pass.clone());
+        self.keys.iter().for_each(|key| match context.model.get_mut(key) {
+            Some(entry) => entry.set_value(pass.clone()),
+            None => msg::no_such_key_warning(key),
         });
+
+        Ok(())
     }
 }
 
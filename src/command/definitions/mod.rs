@@ -1,13 +1,56 @@
+mod audit;
+mod backup;
 mod basic;
+mod completions;
+mod config;
+mod diff;
+mod expiry;
+mod git;
+mod identity;
 mod impexp;
+mod kdf;
 mod misc;
+mod plugin;
+mod recover;
 
+pub use audit::*;
+pub use backup::*;
 pub use basic::*;
+pub use completions::*;
+pub use config::*;
+pub use diff::*;
+pub use expiry::*;
+pub use git::*;
+pub use identity::*;
 pub use impexp::*;
+pub use kdf::*;
 pub use misc::*;
+pub use plugin::*;
+pub use recover::*;
 
-use super::tools;
+use super::{args, tools};
 
 pub trait Command {
-    fn execute(self: Box<Self>, model: &mut crate::context::Context);
+    fn execute(self: Box<Self>, context: &mut pass::context::Context) -> Result<(), pass::error::Error>;
+
+    /// Whether the changes of the command are committed to the store history afterwards.
+    /// Commands recording the history themselves opt out.
+    fn records_history(&self) -> bool {
+        true
+    }
+
+    fn store_access(&self) -> StoreAccess {
+        StoreAccess::Intact
+    }
+}
+
+/// How the store is opened for a command.
+#[derive(Clone, Copy, PartialEq)]
+pub enum StoreAccess {
+    /// Read and saved afterwards, the command fails on a damaged store.
+    Intact,
+    /// What can be read of a damaged store, saving drops the rest.
+    Salvaged,
+    /// Neither read nor saved, the command works on the config or other files.
+    Unused,
 }
@@ -0,0 +1,156 @@
+use pass::context::Context;
+use pass::error::{Error, EXIT_PLUGIN};
+use pass::secret::SecretString;
+
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{self, Stdio};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use zeroize::Zeroizing;
+
+use super::Command;
+
+/// Plugins are executables named "pass-<command>" on PATH.
+pub const PLUGIN_PREFIX: &str = "pass-";
+/// Bumped on incompatible changes of the requests or responses.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+const ENV_STORE: &str = "PASS_STORE";
+const ENV_PROTOCOL: &str = "PASS_PLUGIN_PROTOCOL";
+
+/// Runs an external command. The plugin writes one JSON request per line to its stdout
+/// and reads one JSON response per line from its stdin; its stderr goes to the terminal.
+pub struct Plugin {
+    pub path: PathBuf,
+    pub args: Vec<String>,
+}
+
+/// A request of the plugin, like {"op": "get", "key": "mail"}.
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Request {
+    List,
+    Get { key: String },
+    Add { key: String, value: String },
+    Update { key: String, value: String },
+    Remove { key: String },
+    Rename { key: String, new_key: String },
+    /// Prints a line on stdout of pass, which the plugin doesn't own.
+    Print { text: String },
+}
+
+impl Command for Plugin {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let path = self.path.to_string_lossy().into_owned();
+
+        let mut child = process::Command::new(&self.path)
+            .args(&self.args)
+            .env(ENV_STORE, &context.data_file_path)
+            .env(ENV_PROTOCOL, PROTOCOL_VERSION.to_string())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| Error::read(&path, err))?;
+
+        // A plugin failing midway may have made only part of its changes, they're all undone.
+        let before = context.model.clone();
+
+        let mut responses = child.stdin.take().unwrap();
+        let mut requests = BufReader::new(child.stdout.take().unwrap());
+
+        loop {
+            let mut line = Zeroizing::new(String::new());
+            match requests.read_line(&mut line) {
+                Ok(0) => break,
+                Ok(_) => (),
+                Err(err) => {
+                    context.model = before;
+                    return Err(Error::read(&path, err));
+                },
+            }
+
+            if line.trim().is_empty() { continue; }
+
+            let response = Zeroizing::new(handle(&line, context).to_string());
+
+            // A plugin that stops reading is done, its exit code tells the rest.
+            if writeln!(responses, "{}", *response).and_then(|_| responses.flush()).is_err() {
+                break;
+            }
+        }
+
+        drop(responses);
+        let status = child.wait().map_err(|err| Error::read(&path, err))?;
+        // Its own codes could be taken for the ones of pass, a failure gets one of its own.
+        if !status.success() {
+            context.model = before;
+            context.exit_code = EXIT_PLUGIN;
+        }
+
+        Ok(())
+    }
+}
+
+fn handle(line: &str, context: &mut Context) -> Value {
+    let request = match serde_json::from_str::<Request>(line) {
+        Ok(request) => request,
+        Err(err) => return json!({ "ok": false, "error": format!("invalid request: {}", err) }),
+    };
+
+    let result = match request {
+        Request::List => Ok(json!({ "ok": true, "keys": context.keys() })),
+        Request::Get { key } => context.get(&key).map(|entry| json!({
+            "ok": true,
+            "value": entry.value.expose(),
+            "modified": entry.modified,
+            "max_age": entry.max_age,
+        })),
+        Request::Add { key, value } => context.add(key, SecretString::from(value)).map(|_| json!({ "ok": true })),
+        Request::Update { key, value } => context.update(&key, SecretString::from(value)).map(|_| json!({ "ok": true })),
+        Request::Remove { key } => context.remove(&key).map(|_| json!({ "ok": true })),
+        Request::Rename { key, new_key } => context.rename(&key, new_key).map(|_| json!({ "ok": true })),
+        Request::Print { text } => {
+            println!("{}", text);
+            Ok(json!({ "ok": true }))
+        },
+    };
+
+    result.unwrap_or_else(|err| json!({ "ok": false, "error": err.to_string(), "code": err.exit_code() }))
+}
+
+/// Finds "pass-<name>" in the directories of PATH.
+pub fn find_plugin(name: &str) -> Option<PathBuf> {
+    if name.is_empty() || name.contains(std::path::is_separator) { return None; }
+
+    let file_name = format!("{}{}{}", PLUGIN_PREFIX, name, env::consts::EXE_SUFFIX);
+
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(&file_name))
+        .find(|path| path.is_file())
+}
+
+/// Names of all plugins on PATH, without the prefix.
+pub fn plugin_names() -> Vec<String> {
+    let dirs = match env::var_os("PATH") {
+        Some(path) => env::split_paths(&path).collect::<Vec<PathBuf>>(),
+        None => return vec![],
+    };
+
+    let mut names = dirs.iter()
+        .filter_map(|dir| dir.read_dir().ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter_map(|name| name.strip_prefix(PLUGIN_PREFIX)
+            .map(|n| String::from(n.strip_suffix(env::consts::EXE_SUFFIX).unwrap_or(n))))
+        .filter(|name| !name.is_empty())
+        .collect::<Vec<String>>();
+
+    names.sort();
+    names.dedup();
+    names
+}
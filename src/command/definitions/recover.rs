@@ -0,0 +1,39 @@
+use std::fs;
+
+use pass::context::Context;
+use pass::error::Error;
+
+use super::{
+    Command,
+    StoreAccess,
+    tools::msg,
+};
+
+const DAMAGED_SUFFIX: &str = ".damaged";
+
+/// Rewrites a damaged store with the passwords that could be read, and lists the lines
+/// that couldn't. The damaged file is kept next to the store to fix them by hand.
+pub struct Recover;
+
+impl Command for Recover {
+    fn execute(self: Box<Self>, context: &mut Context) -> Result<(), Error> {
+        let damage = context.damage()?;
+
+        if damage.is_empty() {
+            msg::store_intact();
+            return Ok(());
+        }
+
+        let path = &context.data_file_path;
+        let copy = format!("{}{}", path, DAMAGED_SUFFIX);
+        fs::copy(path, &copy).map_err(|err| Error::write(&copy, err))?;
+
+        msg::recovered(&damage, context.model.len(), &copy);
+
+        Ok(())
+    }
+
+    fn store_access(&self) -> StoreAccess {
+        StoreAccess::Salvaged
+    }
+}
@@ -1,5 +1,9 @@
+pub mod args;
 pub mod builders;
 pub mod resolver;
 
 mod definitions;
 mod tools;
+
+pub use definitions::{Command, StoreAccess};
+pub use tools::dialog::Terminal;
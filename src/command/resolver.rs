@@ -1,36 +1,80 @@
-use crate::command::builders;
-
-pub const CMD_ADD: &str = "add";
-pub const CMD_REMOVE: &str = "rm";
-pub const CMD_UPDATE: &str = "update";
-pub const CMD_LIST: &str = "list";
-pub const CMD_SHOW: &str = "show";
-pub const CMD_EXPORT: &str = "export";
-pub const CMD_IMPORT: &str = "import";
-pub const CMD_RENAME: &str = "rename";
-pub const CMD_CLEAR: &str = "clear";
-pub const CMD_COPY: &str = "copy";
-pub const CMD_MULTIADD: &str = "madd";
-pub const CMD_MULTIREMOVE: &str = "mrm";
-pub const CMD_MULTIUPDATE: &str = "mupd";
-pub const CMD_PASTE: &str = "paste";
-
-pub fn resolve_command(cmd: &str) -> Result<Box<dyn builders::CmdBuilder>, ()> {
-    match cmd {
-        CMD_ADD => Ok(Box::new(builders::AddBuilder)),
-        CMD_REMOVE => Ok(Box::new(builders::RemoveBuilder)),
-        CMD_UPDATE => Ok(Box::new(builders::UpdateBuilder)),
-        CMD_LIST => Ok(Box::new(builders::ListBuilder)),
-        CMD_SHOW => Ok(Box::new(builders::ShowBuilder)),
-        CMD_EXPORT => Ok(Box::new(builders::ExportBuilder)),
-        CMD_IMPORT => Ok(Box::new(builders::ImportBuilder)),
-        CMD_RENAME => Ok(Box::new(builders::RenameBuilder)),
-        CMD_CLEAR => Ok(Box::new(builders::ClearBuilder)),
-        CMD_COPY => Ok(Box::new(builders::CopyBuilder)),
-        CMD_MULTIADD => Ok(Box::new(builders::MultiAddBuilder)),
-        CMD_MULTIREMOVE => Ok(Box::new(builders::MultiRemoveBuilder)),
-        CMD_MULTIUPDATE => Ok(Box::new(builders::MultiUpdateBuilder)),
-        CMD_PASTE => Ok(Box::new(builders::PasteBuilder)),
-        _ => Err(()),
+use crate::command::{args::{self, Opt, Spec}, builders::{self, CmdBuilder}, definitions::{self, Command}};
+
+pub const STORE_OPTION: &str = "--store";
+pub const FORMAT_OPTION: &str = "--format";
+pub const NON_INTERACTIVE_OPTION: &str = "--non-interactive";
+
+/// Options given before the command.
+pub const GLOBAL_SPEC: Spec = Spec {
+    options: &[
+        Opt { name: STORE_OPTION, value: Some("<path>"), help: "password store to use, .data next to the program by default" },
+        Opt { name: FORMAT_OPTION, value: Some("text|json"), help: "output format of list, show, diff, expiring, kdf show and backup list" },
+        Opt { name: NON_INTERACTIVE_OPTION, value: None, help: "fail instead of prompting" },
+        Opt { name: args::HELP_FLAG, value: None, help: "show this help" },
+        Opt { name: args::SHORT_HELP_FLAG, value: None, help: "show this help" },
+    ],
+    positionals: &[],
+};
+
+/// Everything known about a command. Dispatch, help and shell completions are derived from it.
+pub struct CommandInfo {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub summary: &'static str,
+    pub builder: &'static dyn CmdBuilder,
+}
+
+impl CommandInfo {
+    pub fn usage(&self) -> String {
+        format!("{} {}", self.name, args::usage(&self.builder.spec()))
+    }
+
+    fn is_called(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
     }
 }
+
+/// All the commands, in the order help lists them.
+pub const COMMANDS: &[CommandInfo] = &[
+    CommandInfo { name: "add", aliases: &[], summary: "add new password", builder: &builders::AddBuilder },
+    CommandInfo { name: "paste", aliases: &[], summary: "add new password with the clipboard content", builder: &builders::PasteBuilder },
+    CommandInfo { name: "rm", aliases: &["remove"], summary: "remove password", builder: &builders::RemoveBuilder },
+    CommandInfo { name: "update", aliases: &[], summary: "update password", builder: &builders::UpdateBuilder },
+    CommandInfo { name: "list", aliases: &["ls"], summary: "show all keys or the ones due for rotation", builder: &builders::ListBuilder },
+    CommandInfo { name: "tag", aliases: &[], summary: "add or remove tags of a password, to list or export by", builder: &builders::TagBuilder },
+    CommandInfo { name: "show", aliases: &[], summary: "show password by key", builder: &builders::ShowBuilder },
+    CommandInfo { name: "export", aliases: &[], summary: "encrypt passwords using passphrase and export (also as KeePass KDBX)", builder: &builders::ExportBuilder },
+    CommandInfo { name: "import", aliases: &[], summary: "import passwords and decrypt using passphrase", builder: &builders::ImportBuilder },
+    CommandInfo { name: "diff", aliases: &[], summary: "show what differs between the store and an export, or two exports or stores", builder: &builders::DiffBuilder },
+    CommandInfo { name: "rekey", aliases: &[], summary: "change the passphrase or key file of an export", builder: &builders::RekeyBuilder },
+    CommandInfo { name: "kdf", aliases: &[], summary: "tune key derivation cost for this machine", builder: &builders::KdfBuilder },
+    CommandInfo { name: "keygen", aliases: &[], summary: "generate a key file to protect exports with", builder: &builders::KeyGenBuilder },
+    CommandInfo { name: "rename", aliases: &["mv"], summary: "rename specified key", builder: &builders::RenameBuilder },
+    CommandInfo { name: "clear", aliases: &[], summary: "clear password list", builder: &builders::ClearBuilder },
+    CommandInfo { name: "copy", aliases: &["cp"], summary: "copy password to clipboard", builder: &builders::CopyBuilder },
+    CommandInfo { name: "madd", aliases: &[], summary: "add one password for multiple keys", builder: &builders::MultiAddBuilder },
+    CommandInfo { name: "mrm", aliases: &[], summary: "remove multiple passwords", builder: &builders::MultiRemoveBuilder },
+    CommandInfo { name: "mupd", aliases: &[], summary: "update multiple passwords with one value", builder: &builders::MultiUpdateBuilder },
+    CommandInfo { name: "expiring", aliases: &[], summary: "show passwords due for rotation soon", builder: &builders::ExpiringBuilder },
+    CommandInfo { name: "maxage", aliases: &[], summary: "set max password age for a key or the whole vault", builder: &builders::MaxAgeBuilder },
+    CommandInfo { name: "audit", aliases: &[], summary: "check password strength, reuse or breaches (exit code 1 - warnings, 2 - weak, 3 - breached)", builder: &builders::AuditBuilder },
+    CommandInfo { name: "backup", aliases: &[], summary: "list the backups taken before the store changes or restore one", builder: &builders::BackupBuilder },
+    CommandInfo { name: "recover", aliases: &[], summary: "keep what can be read of a damaged store and list the rest", builder: &builders::RecoverBuilder },
+    CommandInfo { name: "identity", aliases: &[], summary: "create or show your key pair for exports sealed to public keys", builder: &builders::IdentityBuilder },
+    CommandInfo { name: "git", aliases: &[], summary: "keep an encrypted history of the store and sync it through git", builder: &builders::GitBuilder },
+    CommandInfo { name: "config", aliases: &[], summary: "show or change settings and aliases", builder: &builders::ConfigBuilder },
+    CommandInfo { name: "completions", aliases: &[], summary: "print a shell completion script", builder: &builders::CompletionsBuilder },
+];
+
+pub fn resolve_command(cmd: &str) -> Result<&'static CommandInfo, ()> {
+    COMMANDS.iter().find(|c| c.is_called(cmd)).ok_or(())
+}
+
+/// Falls back to a "pass-<cmd>" executable on PATH, which gets the arguments as they are.
+pub fn resolve_plugin(cmd: &str, args: Vec<String>) -> Option<Box<dyn Command>> {
+    definitions::find_plugin(cmd).map(|path| Box::new(definitions::Plugin { path, args }) as Box<dyn Command>)
+}
+
+pub fn plugin_names() -> Vec<String> {
+    definitions::plugin_names()
+}
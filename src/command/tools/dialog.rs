@@ -1,44 +1,83 @@
 use std::io;
 use std::io::prelude::*; 
 
-pub fn confirm(message: &str) -> Result<bool, io::Error> {
-    let mut buffer = String::new();
-    let stdin = io::stdin();
+use pass::error::{Error, InputError};
+use pass::merger::{Choice, ConflictResolver, Resolution};
+use pass::prompt::Prompt;
+use pass::secret::SecretString;
 
-    loop {
-        print!("{} (Y/N): ", message);
-        io::stdout().flush().unwrap();
+use super::msg;
+
+/// Asks on the terminal.
+pub struct Terminal;
 
-        match stdin.read_line(&mut buffer) {
-            Ok(_) => match &buffer.trim().to_lowercase()[..] {
+impl Prompt for Terminal {
+    fn confirm(&mut self, message: &str) -> Result<bool, Error> {
+        loop {
+            match &read_answer(&format!("{} (Y/N):", message))?[..] {
                 "yes" | "y" => return Ok(true),
                 "no" | "n" => return Ok(false),
                 _ => (),
-            },
-            Err(err) => return Err(err),
+            }
         }
     }
-}
 
-pub enum PassReadError {
-    SystemError,
-    ConfirmationError,
+    fn password(&mut self, confirm: bool) -> Result<SecretString, Error> {
+        print!("Password: ");
+        io::stdout().flush().unwrap();
+        let pass = match rpassword::read_password() {
+            Ok(p) => SecretString::from(p),
+            Err(_) => return Err(Error::Input(InputError::Terminal)),
+        };
+
+        if !confirm { return Ok(pass) }
+
+        print!("Confirm: ");
+        io::stdout().flush().unwrap();
+        match rpassword::read_password()  {
+            Ok(p) => if SecretString::from(p) == pass { Ok(pass) } else { Err(Error::Input(InputError::Mismatch)) },
+            Err(_) => Err(Error::Input(InputError::Terminal)),
+        } 
+    }
 }
 
-pub fn ask_for_password(confirm: bool) -> Result<String, PassReadError> {
-    print!("Password: ");
-    io::stdout().flush().unwrap();
-    let pass = match rpassword::read_password() {
-        Ok(p) => p,
-        Err(_) => return Err(PassReadError::SystemError),
-    };
+impl ConflictResolver for Terminal {
+    fn resolve(&mut self, collisions: &[String]) -> Result<Resolution, Error> {
+        msg::collision_detected();
+        collisions.iter().for_each(|c| println!("{}", c));
 
-    if !confirm { return Ok(pass) }
+        loop {
+            match &read_answer(msg::strings::CHOOSE_WAY)?[..] {
+                "o" => return Ok(Resolution::KeepOld),
+                "n" => return Ok(Resolution::TakeNew),
+                "m" => {
+                    println!("{}", msg::strings::MERGE_HELP);
+                    return Ok(Resolution::PerKey);
+                },
+                "c" => return Ok(Resolution::Cancel),
+                _ => (),
+            }
+        }
+    }
+
+    fn choose(&mut self, key: &str) -> Result<Choice, Error> {
+        loop {
+            match &read_answer(&format!("{} (O/N):", key))?[..] {
+                "o" => return Ok(Choice::Old),
+                "n" => return Ok(Choice::New),
+                _ => (),
+            }
+        }
+    }
+}
 
-    print!("Confirm: ");
+fn read_answer(question: &str) -> Result<String, Error> {
+    print!("{} ", question);
     io::stdout().flush().unwrap();
-    match rpassword::read_password()  {
-        Ok(p) => if p == pass { Ok(pass) } else { Err(PassReadError::ConfirmationError) },
-        Err(_) => Err(PassReadError::SystemError),
-    } 
+
+    let mut buffer = String::new();
+    match io::stdin().read_line(&mut buffer) {
+        Ok(0) | Err(_) => Err(Error::Input(InputError::Terminal)),
+        Ok(_) => Ok(buffer.trim().to_lowercase()),
+    }
 }
@@ -1,4 +1,4 @@
-pub mod encryption;
 pub mod dialog;
-pub mod merger;
 pub mod msg;
+
+pub use pass::tools::{breach, calibration, encryption, generator, merger, strength};
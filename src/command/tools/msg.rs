@@ -1,60 +1,132 @@
-use super::dialog::PassReadError;
-
-pub fn no_such_key() {
-    println!("No passwords for that key");
-}
+use pass::error::Damage;
+use pass::merger::Summary;
 
 pub fn no_such_key_warning(key: &str) {
     println!("WARNING: no such key - {}", key);
 }
 
-pub fn already_exist() {
-    println!("Password for the given key is already exist");
-}
-
-pub fn failed_renaming() {
-    println!("Could not use already existing key as new name");
+pub fn kdbx_title_collision(key: &str) {
+    println!("WARNING: another key has the same group and title, \"{}\" is titled with the whole key", key);
 }
 
 pub fn collision_detected() {
     println!("Collisions found for the following keys:");
 }
 
-pub fn encryption_failed() {
-    println!("Failed to encrypt data");
+pub fn enter_current_password() {
+    println!("Enter the current passphrase");
 }
 
-pub fn failed_writing(filename: &str) {
-    println!("Could not write to \"{}\"", filename);
+pub fn enter_password_of(path: &std::path::Path) {
+    println!("Enter the passphrase of \"{}\"", path.display());
 }
 
-pub fn decryption_failed() {
-    println!("Failed to decrypt file");
+pub fn enter_new_password() {
+    println!("Enter the new passphrase");
 }
 
-pub fn failed_reading(filename: &str) {
-    println!("Could not read from \"{}\"", filename);
+pub fn audit_summary(total: usize, weak: usize, short: usize, patterned: usize, score: f64, max_score: u8) {
+    println!("Audited {} passwords: {} weak, {} short, {} pattern-based", total, weak, short, patterned);
+    println!("Vault score: {:.1}/{}", score, max_score);
 }
 
-pub fn bad_file() {
-    println!("Invalid import file");
+pub fn no_reused_passwords() {
+    println!("No reused passwords found");
 }
 
-pub fn input_failed() {
-    println!("Could not read the input");
+pub fn rotate_group(keys: &[String]) {
+    println!("Reused by: {}", keys.join(", "));
 }
 
-pub fn pass_read_error(err: PassReadError) {
-    match err {
-        PassReadError::SystemError => input_failed(),
-        PassReadError::ConfirmationError => println!("Password mismatch"),
+pub fn breach_summary(total: usize, breached: usize) {
+    println!("Checked {} passwords: {} found in breaches", total, breached);
+}
+
+pub fn nothing_expiring(days: u64) {
+    println!("No passwords are due for rotation in the next {} days", days);
+}
+
+pub fn merge_summary(summary: &Summary) {
+    for (what, keys) in [("Added", &summary.added), ("Overwritten", &summary.overwritten), ("Removed", &summary.removed), ("Skipped", &summary.skipped)] {
+        match keys.is_empty() {
+            true => println!("{}: 0", what),
+            false => println!("{}: {} ({})", what, keys.len(), keys.join(", ")),
+        }
     }
 }
 
+pub fn calibrating(target: std::time::Duration) {
+    println!("Measuring key derivation for {:.2}s unlock time...", target.as_secs_f64());
+}
+
 pub mod strings {
     pub const CLEAR: &str = "Are you sure you want to delete all your passwords?";
     pub const RM: &str = "Are you sure you want to delete this password?";
     pub const MRM: &str = "Are you sure you want to delete these passwords?";
     pub const CHOOSE_WAY: &str = "You can accept OLD values, NEW values, do detailed MERGE or CANCEL command (O/N/M/C):";
+    pub const ROTATE_GROUP: &str = "Do you want to set a new password for these keys?";
+    pub const RESTORE: &str = "Do you want to replace the store with the backup?";
     pub const MERGE_HELP: &str = "Choose between OLD value and NEW for each collision:";
 }
+
+pub fn clearing_clipboard(seconds: u64) {
+    println!("Copied, the clipboard is cleared in {} seconds", seconds);
+}
+
+pub fn config_file(path: &str) {
+    println!("# {}", path);
+}
+
+pub fn history_created(dir: &std::path::Path, key_path: &std::path::Path) {
+    println!("Created the history in \"{}\"", dir.display());
+    println!("Copy the sync key \"{}\" to the other machines sharing it, it's never pushed", key_path.display());
+}
+
+pub fn identity_created(path: &std::path::Path, public_key: &str, signing_key: &str) {
+    println!("Created the identity in \"{}\", keep it safe", path.display());
+    println!("Public key to share: {}", public_key);
+    println!("Signing key for others to trust: {}", signing_key);
+}
+
+pub fn signed_by(signer: &str, trusted: bool) {
+    match trusted {
+        true => println!("Signed by {}", signer),
+        false => println!("Signed by an unknown key {}", signer),
+    }
+}
+
+pub fn up_to_date() {
+    println!("Already up to date");
+}
+
+pub fn no_backups() {
+    println!("No backups yet, they're taken when the store changes");
+}
+
+pub fn same_as_backup(id: &str) {
+    println!("The store is the same as backup {}", id);
+}
+
+pub fn store_intact() {
+    println!("The store is intact, nothing to recover");
+}
+
+pub fn recovered(damage: &Damage, kept: usize, copy: &str) {
+    if damage.tampered {
+        println!("The store was changed outside of pass, check the passwords it holds");
+    }
+    if damage.incomplete {
+        println!("The end of the store was cut off, passwords may be missing, see \"pass backup list\"");
+    }
+    for line in &damage.unreadable {
+        match &line.key {
+            Some(key) => println!("Could not read line {} (\"{}\")", line.number, key),
+            None => println!("Could not read line {}", line.number),
+        }
+    }
+    println!("Kept {} passwords, the damaged store was copied to \"{}\"", kept, copy);
+}
+
+pub fn json(value: &impl serde::Serialize) {
+    println!("{}", serde_json::to_string_pretty(value).unwrap());
+}
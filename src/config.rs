@@ -0,0 +1,279 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::context;
+use crate::error::Error;
+
+const CONFIG_DIR: &str = "pass";
+const CONFIG_FILENAME: &str = "config.toml";
+const IDENTITY_FILENAME: &str = "identity.txt";
+const SIGNING_KEY_FILENAME: &str = "signing.key";
+const KEY_SEPARATOR: char = '.';
+const CONFIG_SETTING: &str = "config setting";
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(skip)]
+    pub path: String,
+    #[serde(default)]
+    pub store: StoreConfig,
+    #[serde(default)]
+    pub clipboard: ClipboardConfig,
+    #[serde(default)]
+    pub generator: GeneratorConfig,
+    #[serde(default)]
+    pub confirm: ConfirmConfig,
+    /// User-defined commands, like cp = "copy". The value may carry arguments.
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+    #[serde(default)]
+    pub git: GitConfig,
+    /// Trusted signers of exports by name, like alice = "ed25519:...".
+    #[serde(default)]
+    pub signers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub import: ImportConfig,
+    #[serde(default)]
+    pub kdf: KdfConfig,
+    #[serde(default)]
+    pub backup: BackupConfig,
+}
+
+/// Where passwords are kept, unset paths are next to the program.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StoreConfig {
+    pub path: Option<String>,
+    /// Default destination of export and source of import.
+    pub export_path: Option<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClipboardConfig {
+    /// Seconds after which a copied password is cleared, never when unset or 0.
+    pub clear_after: Option<u64>,
+}
+
+/// Defaults of generated passwords.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GeneratorConfig {
+    pub length: Option<usize>,
+    pub symbols: Option<bool>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfirmConfig {
+    /// Ask before removing passwords, true when unset.
+    pub destructive: Option<bool>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GitConfig {
+    /// History repository, next to the store when unset.
+    pub repo: Option<String>,
+    /// Commit after every command changing the store, true when unset.
+    pub auto_commit: Option<bool>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImportConfig {
+    /// Which exports are imported, any when unset.
+    pub signatures: Option<SignaturePolicy>,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignaturePolicy {
+    Any,
+    /// Only signed ones, by anyone.
+    Signed,
+    /// Only the ones signed by a trusted signer.
+    Trusted,
+}
+
+/// Key derivation cost, usually measured by "kdf calibrate".
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KdfConfig {
+    pub memory_kib: Option<u32>,
+    pub iterations: Option<u32>,
+    pub parallelism: Option<u32>,
+    pub pbkdf2_iterations: Option<u32>,
+}
+
+/// Copies of the store kept before it's overwritten, 0 turns a kind off.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BackupConfig {
+    /// The last versions, 10 when unset.
+    pub keep: Option<usize>,
+    /// The last version of each of the last days, 7 when unset.
+    pub daily: Option<usize>,
+    /// The last version of each of the last weeks, 4 when unset.
+    pub weekly: Option<usize>,
+}
+
+impl Config {
+    /// Reads the config, a missing file gives the defaults.
+    pub fn load(path: &str) -> Result<Config, Error> {
+        let mut config = match fs::read_to_string(path) {
+            Ok(s) => toml::from_str::<Config>(&s).map_err(|_| Error::parse(path, "config file"))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Config::default(),
+            Err(err) => return Err(Error::read(path, err)),
+        };
+
+        config.path = String::from(path);
+        Ok(config)
+    }
+
+    /// A config without any settings, saved to the path, for when the file there can't be read.
+    pub fn empty(path: &str) -> Config {
+        Config { path: String::from(path), ..Config::default() }
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let s = toml::to_string(self)
+            .map_err(|err| Error::write(&self.path, io::Error::new(io::ErrorKind::InvalidData, err)))?;
+
+        if let Some(dir) = Path::new(&self.path).parent() {
+            fs::create_dir_all(dir).map_err(|err| Error::write(&self.path, err))?;
+        }
+
+        // A config cut off while written couldn't be loaded, and pass wouldn't start.
+        context::write_atomically(&self.path, s.as_bytes()).map_err(|err| Error::write(&self.path, err))
+    }
+
+    /// The config in the user's config directory, like ~/.config/pass/config.toml.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(CONFIG_DIR).join(CONFIG_FILENAME))
+    }
+
+    /// The age identity of the user, next to the config.
+    pub fn identity_path(&self) -> PathBuf {
+        Path::new(&self.path).with_file_name(IDENTITY_FILENAME)
+    }
+
+    /// The key the user signs exports with, next to the config.
+    pub fn signing_key_path(&self) -> PathBuf {
+        Path::new(&self.path).with_file_name(SIGNING_KEY_FILENAME)
+    }
+
+    /// Every setting that is set, as dotted keys like "store.path" with their values.
+    pub fn settings(&self) -> Vec<(String, String)> {
+        let mut settings = vec![];
+        if let Ok(toml::Value::Table(table)) = toml::Value::try_from(self) {
+            flatten("", &table, &mut settings);
+        }
+        settings
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.settings().into_iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// Sets a dotted key. The value is taken for a number or a boolean when it looks like one
+    /// and the setting takes it, and fails when the key is unknown or doesn't take the value.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        let config = self.with_value(key, parse_value(value))
+            .or_else(|| self.with_value(key, toml::Value::String(String::from(value))))
+            .ok_or_else(|| Error::parse(&format!("{} = {}", key, value), CONFIG_SETTING))?;
+
+        *self = config;
+        Ok(())
+    }
+
+    fn with_value(&self, key: &str, value: toml::Value) -> Option<Config> {
+        let mut root = toml::Value::try_from(self).ok()?;
+
+        let mut parts = key.split(KEY_SEPARATOR).collect::<Vec<&str>>();
+        let last = parts.pop()?;
+
+        let table = parts.into_iter().try_fold(&mut root, |table, part| {
+            table.as_table_mut().map(|t| t.entry(part).or_insert_with(|| toml::Value::Table(toml::map::Map::new())))
+        })?;
+        table.as_table_mut()?.insert(String::from(last), value);
+
+        let mut config = root.try_into::<Config>().ok()?;
+        config.path = self.path.clone();
+        Some(config)
+    }
+
+    /// Removes a dotted key, false when it wasn't set.
+    pub fn unset(&mut self, key: &str) -> Result<bool, Error> {
+        if self.get(key).is_none() { return Ok(false) }
+
+        let mut root = toml::Value::try_from(&*self).map_err(|_| Error::parse(key, CONFIG_SETTING))?;
+
+        let (parent, last) = match key.rsplit_once(KEY_SEPARATOR) {
+            Some((parent, last)) => (parent.split(KEY_SEPARATOR).try_fold(&mut root, |t, part| t.get_mut(part)), last),
+            None => (Some(&mut root), key),
+        };
+
+        if let Some(toml::Value::Table(table)) = parent {
+            table.remove(last);
+        }
+
+        let path = std::mem::take(&mut self.path);
+        *self = root.try_into::<Config>().map_err(|_| Error::parse(key, CONFIG_SETTING))?;
+        self.path = path;
+
+        Ok(true)
+    }
+}
+
+fn flatten(prefix: &str, table: &toml::map::Map<String, toml::Value>, settings: &mut Vec<(String, String)>) {
+    for (name, value) in table {
+        let key = if prefix.is_empty() { name.clone() } else { format!("{}{}{}", prefix, KEY_SEPARATOR, name) };
+
+        match value {
+            toml::Value::Table(inner) => flatten(&key, inner, settings),
+            toml::Value::String(s) => settings.push((key, s.clone())),
+            other => settings.push((key, other.to_string())),
+        }
+    }
+}
+
+fn parse_value(value: &str) -> toml::Value {
+    if let Ok(i) = value.parse::<i64>() {
+        return toml::Value::Integer(i);
+    }
+
+    match value.parse::<bool>() {
+        Ok(b) => toml::Value::Boolean(b),
+        Err(_) => toml::Value::String(String::from(value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let dir = std::env::temp_dir().join(format!("pass-config-{}", std::process::id()));
+        let path = dir.join(CONFIG_FILENAME).to_string_lossy().into_owned();
+
+        let mut config = Config::empty(&path);
+        config.set("clipboard.clear_after", "30").unwrap();
+        config.save().unwrap();
+
+        let loaded = Config::load(&path).unwrap();
+        assert_eq!(loaded.get("clipboard.clear_after").as_deref(), Some("30"));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::write(&path, "[clipboard]\nclear_af").unwrap();
+        assert!(matches!(Config::load(&path), Err(Error::Parse { .. })));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
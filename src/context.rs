@@ -1,75 +1,493 @@
-use std::io::{self, Write, Read};
-use std::fs::File;
+use std::convert::TryFrom;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-pub type PassListModel = std::collections::HashMap::<String, String>;
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
 
+use crate::config::Config;
+use crate::error::{Damage, Error, UnreadableLine};
+use crate::prompt::{NonInteractive, Prompt};
+use crate::secret::SecretString;
+use crate::tools::{backup, encryption::{self, EncryptionParams, KdfParams}, identity::{Identity, Recipients}, kdbx, merger::{self, Base, ConflictResolver, Summary}};
+
+pub type PassListModel = std::collections::HashMap::<String, Entry>;
+
+pub const SECONDS_IN_DAY: u64 = 24 * 60 * 60;
+const SETTING_MAX_AGE: &str = "max_age";
+const SETTING_CHECKSUM: &str = "checksum";
+const SETTING_FORMAT: &str = "format";
+/// Stores of this format start with their format line and end with a checksum line.
+const STORE_FORMAT: u32 = 2;
+// hex of a SHA-256
+const CHECKSUM_LEN: usize = 64;
+// separators and two u64 numbers of a line
+const MAX_NUMBERS_LEN: usize = 48;
+pub const TAG_SEPARATOR: char = ',';
+const EXPORT_FILE: &str = "export file";
+const TEMP_EXTENSION: &str = ".tmp";
+
+#[derive(Clone)]
+pub struct Entry {
+    pub value: SecretString,
+    /// Seconds since the unix epoch, 0 when unknown (entries written by older versions).
+    pub modified: u64,
+    /// Days after which the password should be rotated.
+    pub max_age: Option<u64>,
+    /// Labels to pick entries by, like "shared". Sorted and unique.
+    pub tags: Vec<String>,
+}
+
+impl Entry {
+    pub fn new(value: SecretString) -> Entry {
+        Entry { value, modified: now(), max_age: None, tags: vec![] }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    pub fn set_value(&mut self, value: SecretString) {
+        self.value = value;
+        self.modified = now();
+    }
+
+    /// Seconds left until the entry is due for rotation, negative when overdue.
+    /// None when no max age applies.
+    pub fn expires_in(&self, default_max_age: Option<u64>) -> Option<i64> {
+        let max_age = self.max_age.or(default_max_age)?;
+        // Saturated, the max age may be any number of days.
+        let due = self.modified.saturating_add(max_age.saturating_mul(SECONDS_IN_DAY));
+        Some(i64::try_from(due).unwrap_or(i64::MAX).saturating_sub(i64::try_from(now()).unwrap_or(i64::MAX)))
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Settings {
+    /// Vault-wide max age in days for entries without their own.
+    pub max_age: Option<u64>,
+}
+
+/// An opened password store.
 pub struct Context {
     pub model: PassListModel,
+    pub settings: Settings,
     pub data_file_path: String,
+    pub config: Config,
+    /// Asked when an operation needs confirmation or a password.
+    pub prompt: Box<dyn Prompt>,
+    /// Decides conflicting values when merging.
+    pub resolver: Box<dyn ConflictResolver>,
+    /// The process exit code when everything went fine, set by the commands reporting findings.
+    pub exit_code: i32,
 }
 
 impl Context {
-    pub fn from_file(filename: &str) -> Result<Context, ()> {
-        match File::open(filename) {
-            Ok(mut file) => {
-                let mut buff = String::new();
-                file.read_to_string(&mut buff).unwrap();
-
-                match model_from_string(buff) {
-                    Ok(model) => Ok(Context {
-                        model,
-                        data_file_path: String::from(filename),
-                    }),
-                    Err(_) => Err(()) 
-                }
-            },
-            Err(_) => Err(()),
+    /// Loads the store, a missing one is treated as empty and created on save.
+    /// The context is non-interactive until a prompt and a resolver are set.
+    pub fn open(filename: &str, config: Config) -> Result<Context, Error> {
+        let (model, settings, damage) = read_store(filename)?;
+
+        if !damage.is_empty() {
+            return Err(Error::Damaged { path: String::from(filename), damage });
+        }
+
+        Ok(Context::new(filename, config, model, settings))
+    }
+
+    /// Loads what can be read of a damaged store, saving it drops the rest.
+    pub fn salvage(filename: &str, config: Config) -> Result<Context, Error> {
+        let (model, settings, _) = read_store(filename)?;
+        Ok(Context::new(filename, config, model, settings))
+    }
+
+    /// A context for commands not using the store, it's left empty and must not be saved.
+    pub fn unopened(filename: &str, config: Config) -> Context {
+        Context::new(filename, config, PassListModel::new(), Settings::default())
+    }
+
+    /// What's wrong with the store file as it is now.
+    pub fn damage(&self) -> Result<Damage, Error> {
+        read_store(&self.data_file_path).map(|(_, _, damage)| damage)
+    }
+
+    fn new(filename: &str, config: Config, model: PassListModel, settings: Settings) -> Context {
+        Context {
+            model,
+            settings,
+            data_file_path: String::from(filename),
+            config,
+            prompt: Box::new(NonInteractive),
+            resolver: Box::new(NonInteractive),
+            exit_code: 0,
+        }
+    }
+
+    /// Writes the store when it changed, backing up the version it replaces.
+    pub fn save(&self) -> Result<(), Error> {
+        let path = &self.data_file_path;
+        let contents = self.store_to_string();
+
+        match fs::read(path).map(Zeroizing::new) {
+            Ok(old) if old.as_slice() == contents.as_bytes() => return Ok(()),
+            Ok(_) => backup::create(path, &self.config.backup)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+            Err(err) => return Err(Error::read(path, err)),
+        }
+
+        write_atomically(path, contents.as_bytes()).map_err(|err| Error::write(path, err))
+    }
+
+    /// Keys in alphabetical order.
+    pub fn keys(&self) -> Vec<&String> {
+        let mut keys = self.model.keys().collect::<Vec<&String>>();
+        keys.sort();
+        keys
+    }
+
+    pub fn get(&self, key: &str) -> Result<&Entry, Error> {
+        self.model.get(key).ok_or_else(|| Error::NotFound(String::from(key)))
+    }
+
+    pub fn add(&mut self, key: String, value: SecretString) -> Result<(), Error> {
+        if self.model.contains_key(&key) {
+            return Err(Error::AlreadyExists(key));
         }
+
+        self.model.insert(key, Entry::new(value));
+        Ok(())
     }
 
-    pub fn flush(self) -> Result<(), io::Error> {
-        match File::create(self.data_file_path) {
-            Ok(mut file) => {
-                let lines = self.model.iter()
-                    .map(|(key, value)| format!("{}\u{0}{}\n", key, value).into_bytes())
-                    .flatten()
-                    .collect::<Vec<u8>>();
-    
-                file.write(&lines)?;
-    
+    pub fn update(&mut self, key: &str, value: SecretString) -> Result<(), Error> {
+        match self.model.get_mut(key) {
+            Some(entry) => {
+                entry.set_value(value);
                 Ok(())
             },
-            Err(err) => Err(err),
+            None => Err(Error::NotFound(String::from(key))),
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Result<Entry, Error> {
+        self.model.remove(key).ok_or_else(|| Error::NotFound(String::from(key)))
+    }
+
+    pub fn rename(&mut self, old: &str, new: String) -> Result<(), Error> {
+        if !self.model.contains_key(old) {
+            return Err(Error::NotFound(String::from(old)));
+        }
+
+        if self.model.contains_key(&new) {
+            return Err(Error::AlreadyExists(new));
+        }
+
+        let entry = self.model.remove(old).unwrap();
+        self.model.insert(new, entry);
+        Ok(())
+    }
+
+    /// Asks before a destructive operation, unless the config turns such questions off.
+    pub fn confirm(&mut self, message: &str) -> Result<bool, Error> {
+        match self.config.confirm.destructive.unwrap_or(true) {
+            true => self.prompt.confirm(message),
+            false => Ok(true),
+        }
+    }
+
+    /// Merges the entries in, conflicts are decided by the resolver. None when it cancelled.
+    pub fn merge(&mut self, model: PassListModel) -> Result<Option<Summary>, Error> {
+        merger::merge(model, &mut self.model, self.resolver.as_mut())
+    }
+
+    /// Merges a store that was in the state of the base when last synced, taking over
+    /// its changes and removals. Only conflicting changes go to the resolver.
+    pub fn merge_with_base(&mut self, model: PassListModel, base: &Base) -> Result<Option<Summary>, Error> {
+        merger::merge_with_base(model, &mut self.model, base, self.resolver.as_mut())
+    }
+
+    /// Encrypts the whole store, or only the entries of the keys, as read back by read_export.
+    pub fn export(&self, keys: Option<&[String]>, pass: &str, key_file: Option<&[u8]>, params: EncryptionParams) -> Result<Vec<u8>, Error> {
+        let lines = match keys {
+            Some(keys) => entries_to_string(&self.selected(keys), None),
+            None => self.store_to_string(),
+        };
+
+        Ok(encryption::encrypt(lines.as_bytes(), pass, key_file, params)?)
+    }
+
+    /// Seals the whole store, or only the entries of the keys, to the public keys.
+    /// Read back by read_sealed_export.
+    pub fn export_to(&self, keys: Option<&[String]>, recipients: &Recipients) -> Result<Vec<u8>, Error> {
+        let lines = match keys {
+            Some(keys) => entries_to_string(&self.selected(keys), None),
+            None => self.store_to_string(),
+        };
+
+        recipients.encrypt(lines.as_bytes())
+    }
+
+    /// Builds a KeePass database of all the entries, or only the ones of the keys,
+    /// with the keys titled with the whole key since another one took their title.
+    pub fn export_kdbx(&self, keys: Option<&[String]>, pass: &str, key_file: Option<&[u8]>, kdf: KdfParams) -> Result<(Vec<u8>, Vec<String>), Error> {
+        let export = |model: &PassListModel| -> Result<(Vec<u8>, Vec<String>), Error> {
+            let renamed = kdbx::renamed(model).into_iter().map(String::from).collect();
+            Ok((kdbx::export(model, pass, key_file, kdf)?, renamed))
+        };
+
+        match keys {
+            Some(keys) => export(&self.selected(keys)),
+            None => export(&self.model),
         }
     }
+
+    /// A copy of the entries of the keys, the missing ones are left out.
+    pub fn selected(&self, keys: &[String]) -> PassListModel {
+        keys.iter()
+            .filter_map(|key| self.model.get_key_value(key))
+            .map(|(key, entry)| (key.clone(), entry.clone()))
+            .collect()
+    }
+
+    /// The format line and the entries, followed by the checksum of those lines.
+    fn store_to_string(&self) -> Zeroizing<String> {
+        let format_line = format_line();
+        let lines = entries_to_string(&self.model, self.settings.max_age);
+
+        // Copied into a buffer sized up front too, growing the first one would leave a copy behind.
+        let capacity = format_line.len() + lines.len() + SETTING_CHECKSUM.len() + CHECKSUM_LEN + 3;
+        let mut store = Zeroizing::new(String::with_capacity(capacity));
+        store.push_str(&format_line);
+        store.push_str(&lines);
+
+        let checksum = checksum(store.as_bytes());
+        let _ = writeln!(store, "\u{0}{}\u{0}{}", SETTING_CHECKSUM, checksum);
+        store
+    }
+}
+
+// Sized up front, so the buffer holding the passwords is never reallocated.
+fn entries_to_string(model: &PassListModel, max_age: Option<u64>) -> Zeroizing<String> {
+    let capacity = model.iter()
+        .map(|(key, entry)| key.len() + entry.value.expose().len() + MAX_NUMBERS_LEN + entry.tags.iter().map(|t| t.len() + 1).sum::<usize>())
+        .sum::<usize>() + MAX_NUMBERS_LEN;
+    let mut lines = Zeroizing::new(String::with_capacity(capacity));
+
+    if let Some(days) = max_age {
+        let _ = writeln!(lines, "\u{0}{}\u{0}{}", SETTING_MAX_AGE, days);
+    }
+
+    // Sorted, so an unchanged store is written the same way.
+    let mut entries = model.iter().collect::<Vec<(&String, &Entry)>>();
+    entries.sort_by_key(|(key, _)| *key);
+
+    entries.into_iter().for_each(|(key, entry)| {
+        let max_age = entry.max_age.map(|d| d.to_string()).unwrap_or_default();
+        let _ = write!(lines, "{}\u{0}{}\u{0}{}\u{0}{}", key, entry.value.expose(), entry.modified, max_age);
+
+        // Left out when empty, an entry without tags is written as the same four fields it had
+        // before there were tags, and parse_line reads the missing field as no tags.
+        if !entry.tags.is_empty() {
+            let _ = write!(lines, "\u{0}{}", entry.tags.join(&TAG_SEPARATOR.to_string()));
+        }
+        lines.push('\n');
+    });
+
+    lines
+}
+
+/// Decrypts an export file written by Context::export.
+/// The path is only used in the errors.
+pub fn read_export(data: &[u8], path: &str, pass: &str, key_file: Option<&[u8]>) -> Result<PassListModel, Error> {
+    read_store_export(data, path, pass, key_file).map(|(model, _settings)| model)
+}
+
+/// Like read_export, along with the store settings an export of the whole store carries.
+pub fn read_store_export(data: &[u8], path: &str, pass: &str, key_file: Option<&[u8]>) -> Result<(PassListModel, Settings), Error> {
+    let decrypted = encryption::decrypt(data, pass, key_file)?;
+
+    std::str::from_utf8(&decrypted).map_err(|_| ())
+        .and_then(store_from_string)
+        .map_err(|_| Error::parse(path, EXPORT_FILE))
+}
+
+/// Opens an export written by Context::export_to with our identity.
+pub fn read_sealed_export(data: &[u8], path: &str, identity: &Identity) -> Result<PassListModel, Error> {
+    let decrypted = identity.decrypt(data)?;
+
+    std::str::from_utf8(&decrypted).map_err(|_| ())
+        .and_then(store_from_string)
+        .map(|(model, _settings)| model)
+        .map_err(|_| Error::parse(path, EXPORT_FILE))
+}
+
+fn store_from_string(s: &str) -> Result<(PassListModel, Settings), ()> {
+    match parse_store(s.as_bytes()) {
+        (model, settings, damage) if damage.is_empty() => Ok((model, settings)),
+        _ => Err(()),
+    }
 }
 
-pub fn model_from_string(s: String) -> Result<PassListModel, ()> {
-    let lines = s
-        .split("\n")
-        .map(|x| String::from(x))
-        .filter(|x| !x.is_empty())
-        .collect::<Vec<String>>();
+fn read_store(filename: &str) -> Result<(PassListModel, Settings, Damage), Error> {
+    match fs::read(filename).map(Zeroizing::new) {
+        Ok(data) => Ok(parse_store(&data)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok((PassListModel::new(), Settings::default(), Damage::default())),
+        Err(err) => Err(Error::read(filename, err)),
+    }
+}
 
+/// Parses the store: one "key\0value\0modified\0max_age\0tags" line per entry,
+/// where the last three fields may be missing, and "\0name\0value" lines for settings.
+/// Tags are separated by commas. Stores starting with a "\0format\0<n>" line end with a
+/// "\0checksum\0<sha-256>" line covering the lines before it, stores written by older
+/// versions have neither.
+/// Every line that can be read is kept, the damage tells about the others.
+fn parse_store(data: &[u8]) -> (PassListModel, Settings, Damage) {
     let mut model = PassListModel::new();
+    let mut settings = Settings::default();
+    let mut damage = Damage::default();
 
-    for line in lines {
-        let mut key_value = line
-            .split("\u{0}")
-            .map(|x| String::from(x))
-            .collect::<Vec<String>>();
+    let (lines, checksum_line) = split_checksum(data);
+    match checksum_line {
+        Some(expected) => damage.tampered = expected != checksum(lines).as_bytes(),
+        // Cut off while being written, or by hand.
+        None => damage.incomplete = data.starts_with(format_line().as_bytes()),
+    }
+
+    for (i, line) in lines.split(|b| *b == b'\n').enumerate() {
+        if line.is_empty() { continue }
+
+        let parsed = std::str::from_utf8(line).map_err(|_| ())
+            .and_then(|line| parse_line(line, &mut model, &mut settings));
 
-        if key_value.len() != 2 {
-            return Err(());
+        if parsed.is_err() {
+            damage.unreadable.push(UnreadableLine { number: i + 1, key: key_of(line) });
         }
+    }
+
+    (model, settings, damage)
+}
+
+/// The fields are borrowed from the input, only the value is copied, into a secret string.
+fn parse_line(line: &str, model: &mut PassListModel, settings: &mut Settings) -> Result<(), ()> {
+    let fields = line
+        .split('\u{0}')
+        .collect::<Vec<&str>>();
+
+    if fields.len() < 2 || fields.len() > 5 {
+        return Err(());
+    }
+
+    if fields[0].is_empty() {
+        return match (fields[1], fields.get(2)) {
+            (SETTING_MAX_AGE, Some(days)) => {
+                settings.max_age = Some(parse_number(days)?);
+                Ok(())
+            },
+            (SETTING_FORMAT, Some(format)) if *format == STORE_FORMAT.to_string() => Ok(()),
+            _ => Err(()),
+        };
+    }
+
+    let key = String::from(fields[0]);
+    let value = SecretString::from(fields[1]);
+
+    let modified = match fields.get(2) {
+        Some(m) => parse_number(m)?,
+        None => 0,
+    };
+
+    let max_age = match fields.get(3) {
+        Some(days) if !days.is_empty() => Some(parse_number(days)?),
+        _ => None,
+    };
+
+    let tags = match fields.get(4) {
+        Some(tags) => tags.split(TAG_SEPARATOR).filter(|t| !t.is_empty()).map(String::from).collect(),
+        None => vec![],
+    };
+
+    model.insert(key, Entry { value, modified, max_age, tags });
+    Ok(())
+}
+
+/// The lines before the checksum line and its value, when the store ends with one.
+fn split_checksum(data: &[u8]) -> (&[u8], Option<&[u8]>) {
+    let end = data.iter().rposition(|b| *b != b'\n').map_or(0, |i| i + 1);
+    let start = data[..end].iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+    let prefix = format!("\u{0}{}\u{0}", SETTING_CHECKSUM);
+
+    match data[start..end].strip_prefix(prefix.as_bytes()) {
+        Some(checksum) => (&data[..start], Some(checksum)),
+        None => (data, None),
+    }
+}
+
+fn format_line() -> String {
+    format!("\u{0}{}\u{0}{}\n", SETTING_FORMAT, STORE_FORMAT)
+}
+
+fn checksum(lines: &[u8]) -> String {
+    Sha256::digest(lines).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Not the whole line, it may hold a password.
+fn key_of(line: &[u8]) -> Option<String> {
+    let end = line.iter().position(|b| *b == 0)?;
+    std::str::from_utf8(&line[..end]).ok().filter(|key| !key.is_empty()).map(String::from)
+}
+
+fn parse_number(s: &str) -> Result<u64, ()> {
+    s.parse().map_err(|_| ())
+}
+
+/// Whether the data starts the way stores of the current format do.
+pub fn has_store_format(data: &[u8]) -> bool {
+    data.starts_with(format_line().as_bytes())
+}
+
+/// Writes the data next to the destination first and then moves it over,
+/// so the destination never holds a partially written file. It keeps its permissions.
+pub fn write_atomically(path: &str, data: &[u8]) -> io::Result<()> {
+    let temp_path = format!("{}{}", path, TEMP_EXTENSION);
+    let permissions = fs::metadata(path).map(|m| m.permissions()).ok();
+
+    let result = File::create(&temp_path)
+        .and_then(|mut f| {
+            // Before writing, the data never sits in a file others may read.
+            if let Some(permissions) = permissions {
+                f.set_permissions(permissions)?;
+            }
+            f.write_all(data).and_then(|_| f.sync_all())
+        })
+        .and_then(|_| fs::rename(&temp_path, path))
+        .and_then(|_| sync_dir(path));
+
+    if result.is_err() && Path::new(&temp_path).exists() {
+        let _ = fs::remove_file(&temp_path);
+    }
 
-        let key = std::mem::replace(&mut key_value[0], String::new());
-        let value = std::mem::replace(&mut key_value[1], String::new());
+    result
+}
 
-        model.insert(key, value);
+// The rename is only durable once the directory holding the file is written too.
+#[cfg(unix)]
+fn sync_dir(path: &str) -> io::Result<()> {
+    match Path::new(path).parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => File::open(dir)?.sync_all(),
+        None => File::open(".")?.sync_all(),
     }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_path: &str) -> io::Result<()> {
+    Ok(())
+}
 
-    Ok(model)
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
 }
 
 pub fn merge_models(f: PassListModel, s: &mut PassListModel) {
@@ -78,10 +496,140 @@ pub fn merge_models(f: PassListModel, s: &mut PassListModel) {
 
 pub fn find_collisions<'a>(f: &'a PassListModel, s: &PassListModel) -> Vec<&'a String> {
     f.iter()
-        .filter(|(key, value)| match s.get(*key) {
-            Some(v) => v != *value,
+        .filter(|(key, entry)| match s.get(*key) {
+            Some(e) => e.value != entry.value,
             None => false,
         })
         .map(|(key, _value)| key)
         .collect::<Vec<&'a String>>()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(value: &str, modified: u64, max_age: Option<u64>, tags: &[&str]) -> Entry {
+        Entry { value: SecretString::from(value), modified, max_age, tags: tags.iter().map(|t| String::from(*t)).collect() }
+    }
+
+    fn store() -> Zeroizing<String> {
+        let mut context = Context::unopened("store", Config::default());
+        context.model.insert(String::from("mail"), entry("secret", 100, Some(90), &["shared", "work"]));
+        context.model.insert(String::from("bank"), entry("other\u{1}secret", 200, None, &[]));
+        context.settings.max_age = Some(30);
+        context.store_to_string()
+    }
+
+    fn unreadable_lines(damage: &Damage) -> Vec<(usize, Option<&str>)> {
+        damage.unreadable.iter().map(|line| (line.number, line.key.as_deref())).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let store = store();
+        assert!(has_store_format(store.as_bytes()));
+
+        let (model, settings, damage) = parse_store(store.as_bytes());
+        assert!(damage.is_empty());
+        assert_eq!(settings.max_age, Some(30));
+        assert_eq!(model.len(), 2);
+
+        let mail = &model["mail"];
+        assert_eq!(mail.value.expose(), "secret");
+        assert_eq!((mail.modified, mail.max_age), (100, Some(90)));
+        assert_eq!(mail.tags, vec!["shared", "work"]);
+
+        let bank = &model["bank"];
+        assert_eq!(bank.value.expose(), "other\u{1}secret");
+        assert_eq!((bank.modified, bank.max_age), (200, None));
+        assert!(bank.tags.is_empty());
+    }
+
+    #[test]
+    fn split_checksum_line() {
+        let store = store();
+        let (lines, checksum_line) = split_checksum(store.as_bytes());
+
+        assert!(lines.ends_with(b"\n"));
+        assert_eq!(checksum_line, Some(checksum(lines).as_bytes()));
+
+        assert_eq!(split_checksum(lines), (lines, None));
+        assert_eq!(split_checksum(b""), (&b""[..], None));
+    }
+
+    #[test]
+    fn tampered() {
+        let store = store().replace("secret", "public");
+        let (model, _, damage) = parse_store(store.as_bytes());
+
+        assert!(damage.tampered && !damage.incomplete && damage.unreadable.is_empty());
+        assert_eq!(model["mail"].value.expose(), "public");
+    }
+
+    #[test]
+    fn incomplete() {
+        let store = store();
+        let (lines, _) = split_checksum(store.as_bytes());
+        // cut off within the tags of the last entry, the line itself is still readable
+        let (model, _, damage) = parse_store(&lines[..lines.len() - 4]);
+
+        assert!(damage.incomplete && !damage.tampered && damage.unreadable.is_empty());
+        assert_eq!(model["mail"].tags, vec!["shared", "w"]);
+    }
+
+    #[test]
+    fn unreadable() {
+        let mut data = format!("{}bank\u{0}secret\u{0}soon\nmail\u{0}secret\nkey only\n\u{0}unknown\u{0}1\n", format_line()).into_bytes();
+        data.extend_from_slice(b"\xff\xfe\n");
+        data.extend_from_slice(format!("\u{0}{}\u{0}{}\n", SETTING_CHECKSUM, checksum(&data)).as_bytes());
+
+        let (model, _, damage) = parse_store(&data);
+
+        assert!(!damage.tampered && !damage.incomplete);
+        assert_eq!(unreadable_lines(&damage), vec![(2, Some("bank")), (4, None), (5, None), (6, None)]);
+        assert_eq!(model.keys().collect::<Vec<&String>>(), vec!["mail"]);
+    }
+
+    #[test]
+    fn other_format() {
+        let store = store().replacen(&format_line(), &format!("\u{0}{}\u{0}{}\n", SETTING_FORMAT, STORE_FORMAT + 1), 1);
+        let (_, _, damage) = parse_store(store.as_bytes());
+
+        assert_eq!(unreadable_lines(&damage), vec![(1, None)]);
+    }
+
+    #[test]
+    fn legacy_store() {
+        let (model, settings, damage) = parse_store(b"\x00max_age\x0030\nmail\x00secret\x00100\x00\nbank\x00other\n");
+
+        assert!(damage.is_empty());
+        assert_eq!(settings.max_age, Some(30));
+        assert_eq!(model["mail"].modified, 100);
+        assert_eq!(model["bank"].modified, 0);
+    }
+
+    #[test]
+    fn open_and_salvage() {
+        let path = std::env::temp_dir().join(format!("pass-context-test-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let store = store();
+        let (lines, _) = split_checksum(store.as_bytes());
+        fs::write(path, lines).unwrap();
+
+        let opened = Context::open(path, Config::default());
+        let salvaged = Context::salvage(path, Config::default());
+        let _ = fs::remove_file(path);
+
+        assert!(matches!(opened, Err(Error::Damaged { damage: Damage { incomplete: true, .. }, .. })));
+        assert_eq!(salvaged.unwrap().model.len(), 2);
+    }
+
+    #[test]
+    fn expires_in_saturates() {
+        let entry = entry("secret", u64::MAX, Some(u64::MAX), &[]);
+        assert_eq!(entry.expires_in(None), Some(i64::MAX - now() as i64));
+        assert_eq!(entry.expires_in(Some(1)), Some(i64::MAX - now() as i64));
+        assert_eq!(Entry::new(SecretString::from("secret")).expires_in(None), None);
+    }
+}
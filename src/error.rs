@@ -0,0 +1,189 @@
+use std::fmt;
+use std::io;
+
+// Exit codes, 1-3 are left to the audit findings.
+pub const EXIT_USAGE: i32 = 10;
+pub const EXIT_NOT_FOUND: i32 = 11;
+pub const EXIT_ALREADY_EXISTS: i32 = 12;
+pub const EXIT_INPUT: i32 = 13;
+pub const EXIT_IO: i32 = 14;
+pub const EXIT_PARSE: i32 = 15;
+pub const EXIT_CRYPTO: i32 = 16;
+pub const EXIT_GIT: i32 = 17;
+/// A plugin failed, whatever its own exit code was.
+pub const EXIT_PLUGIN: i32 = 18;
+
+#[derive(Debug)]
+pub enum Error {
+    Io { action: IoAction, path: String, source: io::Error },
+    /// The file at the path isn't what was expected.
+    Parse { path: String, expected: &'static str },
+    Crypto(CryptoError),
+    Input(InputError),
+    /// No entry for the key.
+    NotFound(String),
+    /// An entry or file with the name exists already.
+    AlreadyExists(String),
+    /// A git command failed, with what it printed.
+    Git(String),
+    /// Keys changed on both sides of a merge that wasn't allowed to pick one.
+    Conflict(Vec<String>),
+    Signature(SignatureError),
+    /// The store was changed outside of pass, or has lines that can't be read.
+    Damaged { path: String, damage: Damage },
+}
+
+#[derive(Debug)]
+pub enum IoAction {
+    Read,
+    Write,
+}
+
+#[derive(Debug)]
+pub enum CryptoError {
+    Encrypt,
+    /// Wrong passphrase or key file, or a damaged file.
+    Decrypt,
+    KeyDerivation,
+    /// Not an encrypted export, or one written by a newer version.
+    Format,
+    /// Sealed to public keys, there's no passphrase to change.
+    Sealed,
+}
+
+#[derive(Debug)]
+pub enum SignatureError {
+    /// The export was changed after signing, or the signature is damaged.
+    Invalid,
+    /// The config asks for signed exports.
+    Missing,
+    /// The config asks for trusted signers, the public key of this one isn't among them.
+    Untrusted(String),
+    /// Changing a signed export would break the signature.
+    Signed,
+}
+
+/// What's wrong with a store file, nothing when it's intact.
+#[derive(Debug, Default)]
+pub struct Damage {
+    /// The checksum doesn't match the lines it covers.
+    pub tampered: bool,
+    /// The checksum line is missing, the end of the store was cut off.
+    pub incomplete: bool,
+    pub unreadable: Vec<UnreadableLine>,
+}
+
+#[derive(Debug)]
+pub struct UnreadableLine {
+    /// Counted from 1.
+    pub number: usize,
+    /// The key the line starts with, when it has one.
+    pub key: Option<String>,
+}
+
+impl Damage {
+    pub fn is_empty(&self) -> bool {
+        !self.tampered && !self.incomplete && self.unreadable.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub enum InputError {
+    Terminal,
+    Clipboard,
+    Mismatch,
+    KeyFileRequired,
+    NotInteractive,
+    /// No identity to open an export sealed to public keys.
+    NoIdentity,
+    /// The export is sealed to other public keys than ours.
+    NotRecipient,
+    /// Clearing the store after an export sealed to other public keys than ours.
+    ClearForOthers,
+}
+
+impl Error {
+    pub fn read(path: &str, source: io::Error) -> Error {
+        Error::Io { action: IoAction::Read, path: String::from(path), source }
+    }
+
+    pub fn write(path: &str, source: io::Error) -> Error {
+        Error::Io { action: IoAction::Write, path: String::from(path), source }
+    }
+
+    pub fn parse(path: &str, expected: &'static str) -> Error {
+        Error::Parse { path: String::from(path), expected }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io { .. } => EXIT_IO,
+            Error::Parse { .. } | Error::Damaged { .. } => EXIT_PARSE,
+            Error::Crypto(_) | Error::Signature(_) => EXIT_CRYPTO,
+            Error::Input(_) => EXIT_INPUT,
+            Error::NotFound(_) => EXIT_NOT_FOUND,
+            Error::AlreadyExists(_) | Error::Conflict(_) => EXIT_ALREADY_EXISTS,
+            Error::Git(_) => EXIT_GIT,
+        }
+    }
+}
+
+impl From<CryptoError> for Error {
+    fn from(err: CryptoError) -> Error {
+        Error::Crypto(err)
+    }
+}
+
+impl From<InputError> for Error {
+    fn from(err: InputError) -> Error {
+        Error::Input(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { action: IoAction::Read, path, source } => write!(f, "Could not read from \"{}\": {}", path, source),
+            Error::Io { action: IoAction::Write, path, source } => write!(f, "Could not write to \"{}\": {}", path, source),
+            Error::Parse { path, expected } => write!(f, "\"{}\" is not a valid {}", path, expected),
+            Error::Crypto(CryptoError::Encrypt) => write!(f, "Failed to encrypt data"),
+            Error::Crypto(CryptoError::Decrypt) => write!(f, "Failed to decrypt file, check the passphrase and key file"),
+            Error::Crypto(CryptoError::KeyDerivation) => write!(f, "Key derivation failed, check its parameters"),
+            Error::Crypto(CryptoError::Format) => write!(f, "Unknown encrypted file format"),
+            Error::Crypto(CryptoError::Sealed) => write!(f, "The file is sealed to public keys, not a passphrase, export it again with --to to change who can open it"),
+            Error::Input(InputError::Terminal) => write!(f, "Could not read the input"),
+            Error::Input(InputError::Clipboard) => write!(f, "Could not access the clipboard"),
+            Error::Input(InputError::Mismatch) => write!(f, "Password mismatch"),
+            Error::Input(InputError::KeyFileRequired) => write!(f, "This file is protected with a key file, pass it with --key-file"),
+            Error::Input(InputError::NotInteractive) => write!(f, "Input is needed, but not available"),
+            Error::Input(InputError::NoIdentity) => write!(f, "No identity found, create one with \"pass identity create\""),
+            Error::Input(InputError::NotRecipient) => write!(f, "The file wasn't encrypted for your identity"),
+            Error::Input(InputError::ClearForOthers) => write!(f, "The store can only be cleared when you can open the export, add your public key to --to"),
+            Error::NotFound(key) => write!(f, "No passwords for \"{}\"", key),
+            Error::AlreadyExists(name) => write!(f, "\"{}\" already exists", name),
+            Error::Git(message) => write!(f, "Git failed: {}", message),
+            Error::Signature(SignatureError::Invalid) => write!(f, "The signature doesn't match, the file was changed after signing"),
+            Error::Signature(SignatureError::Missing) => write!(f, "The file isn't signed, the config only allows signed imports"),
+            Error::Signature(SignatureError::Signed) => write!(f, "The file is signed, changing it would break the signature, export it again with --sign instead"),
+            Error::Signature(SignatureError::Untrusted(key)) => write!(f, "The file is signed by {}, which isn't a trusted signer", key),
+            Error::Conflict(keys) => write!(f, "Conflicting passwords for {}", keys.join(", ")),
+            Error::Damaged { path, damage } if damage.incomplete && damage.unreadable.is_empty() =>
+                write!(f, "\"{}\" is incomplete, its end was cut off. Restore a backup, or run \"pass recover\" to keep what's left", path),
+            Error::Damaged { path, damage } if damage.unreadable.is_empty() =>
+                write!(f, "\"{}\" was changed outside of pass, its checksum doesn't match. Check it, then run \"pass recover\" to accept it", path),
+            Error::Damaged { path, damage } => {
+                let lines = damage.unreadable.iter().map(|l| l.number.to_string()).collect::<Vec<String>>();
+                write!(f, "Could not read line {} of \"{}\", run \"pass recover\" to keep the passwords that can be read", lines.join(", "), path)
+            },
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,33 @@
+//! The password store behind the `pass` command line tool.
+//!
+//! A [`Context`] is an opened store: a map of keys to [`Entry`] values together
+//! with the vault settings. It can be changed in memory and saved back, exported
+//! to an encrypted file and merged with the entries of another one.
+//!
+//! Nothing in the library talks to the terminal. Operations that may need a decision
+//! from the user take it from the [`prompt::Prompt`] and [`merger::ConflictResolver`]
+//! held by the context, and by default they fail instead of asking.
+//!
+//! ```no_run
+//! use pass::{Context, Config, SecretString};
+//!
+//! let mut store = Context::open(".data", Config::default())?;
+//! store.add(String::from("mail"), SecretString::from("correct horse battery staple"))?;
+//! println!("{}", store.get("mail")?.value.expose());
+//! store.save()?;
+//! # Ok::<(), pass::Error>(())
+//! ```
+
+pub mod config;
+pub mod context;
+pub mod error;
+pub mod prompt;
+pub mod secret;
+pub mod tools;
+
+pub use tools::{encryption, merger};
+
+pub use config::Config;
+pub use context::{Context, Entry, PassListModel};
+pub use error::Error;
+pub use secret::SecretString;
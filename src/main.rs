@@ -1,80 +1,250 @@
-mod context;
 mod command;
 
-use std::env::{self, Args};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-use command::{resolver::*, builders::CmdBuilder};
-use context::Context;
+use command::{args::{self, ArgError, OutputFormat, Spec}, resolver::*, Command, StoreAccess, Terminal};
+use pass::{Config, Context, Error, error::EXIT_USAGE, secret};
+use pass::tools::{changes::Snapshot, history::History};
 
 const FILENAME: &str = ".data";
+const CONFIG_FILENAME: &str = "config.toml";
+const CONFIG_COMMAND: &str = "config";
 
 fn main() {
-    match parse_args(std::env::args()) {
-        Ok(ParseResult{cmd, args, path}) => match resolve_command(&cmd) {
-            Ok(builder) => match builder.build(args) {
-                Ok(command) => {
-                    let mut context = Context::from_file(&path).unwrap();
+    secret::harden_process();
 
-                    command.execute(&mut context);
+    let (globals, rest) = match args::parse_leading(&GLOBAL_SPEC, env::args().skip(1).collect()) {
+        Ok(parsed) => parsed,
+        Err(err) => usage_error(&err, "pass", &GLOBAL_SPEC),
+    };
 
-                    context.flush().unwrap();
-                },
-                Err(_) => command_usage(&cmd, builder)
+    let help_asked = globals.flag(args::HELP_FLAG) || globals.flag(args::SHORT_HELP_FLAG);
+
+    let globals = match parse_globals(globals) {
+        Ok(globals) => globals,
+        Err(err) => usage_error(&err, "pass", &GLOBAL_SPEC),
+    };
+
+    let config_path = match config_path() {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(err.exit_code());
+        },
+    };
+
+    let config = match Config::load(&config_path) {
+        Ok(config) => config,
+        // The config command still runs, or a broken config couldn't be fixed with it.
+        Err(err) if rest.first().is_some_and(|cmd| is_config_command(cmd)) => {
+            eprintln!("{}", err);
+            eprintln!("Using an empty config, the next change replaces the file");
+            Config::empty(&config_path)
+        },
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(err.exit_code());
+        },
+    };
+
+    if rest.is_empty() {
+        help(&config);
+        std::process::exit(if help_asked { 0 } else { EXIT_USAGE });
+    }
+
+    let (cmd, rest) = expand_alias(&config, rest);
+
+    let command = match resolve_command(&cmd) {
+        Ok(info) => {
+            if args::is_help(&rest) {
+                command_help(info);
+                std::process::exit(0);
+            }
+
+            match args::parse(&info.builder.spec(), rest).and_then(|mut args| {
+                args.output = globals.output;
+                info.builder.build(args)
+            }) {
+                Ok(command) => command,
+                Err(err) => usage_error(&err, &format!("pass {}", info.name), &info.builder.spec()),
+            }
+        },
+        Err(_) => match resolve_plugin(&cmd, rest) {
+            Some(plugin) => plugin,
+            None => {
+                unknown_command(&cmd);
+                std::process::exit(EXIT_USAGE);
             },
-            Err(_) => unknown_command(&cmd),
         },
-        Err(_) => help(),
+    };
+
+    let exit_code = match run(command, config, &globals) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("{}", err);
+            err.exit_code()
+        },
+    };
+
+    std::process::exit(exit_code);
+}
+
+/// Runs the command against the store and saves it, also when the command fails midway.
+/// Commands not using the store leave it alone.
+fn run(command: Box<dyn Command>, config: Config, globals: &Globals) -> Result<i32, Error> {
+    let path = match (&globals.path, &config.store.path) {
+        (Some(path), _) | (None, Some(path)) => path.clone(),
+        (None, None) => path_str(&exe_dir().join(FILENAME)),
+    };
+
+    let access = command.store_access();
+    let mut context = match access {
+        StoreAccess::Intact => Context::open(&path, config)?,
+        StoreAccess::Salvaged => Context::salvage(&path, config)?,
+        StoreAccess::Unused => Context::unopened(&path, config),
+    };
+
+    if globals.interactive {
+        context.prompt = Box::new(Terminal);
+        context.resolver = Box::new(Terminal);
+    }
+
+    if access == StoreAccess::Unused {
+        return command.execute(&mut context).map(|_| context.exit_code);
+    }
+
+    let history = match command.records_history() && context.config.git.auto_commit.unwrap_or(true) {
+        true => History::open(&context.data_file_path, &context.config)?.map(|h| (h, Snapshot::of(&context))),
+        false => None,
+    };
+
+    let result = command.execute(&mut context);
+
+    context.save()?;
+
+    if let Some((history, before)) = history {
+        let changes = before.changes(&context);
+        if !changes.is_empty() {
+            history.commit(&context, &changes.message())?;
+        }
     }
+
+    result.map(|_| context.exit_code)
 }
 
-struct ParseResult {
-    cmd: String,
-    args: Vec<String>,
-    path: String,
+struct Globals {
+    /// Store given on the command line, it takes precedence over the config.
+    path: Option<String>,
+    output: OutputFormat,
+    interactive: bool,
 }
 
-fn parse_args(mut args: Args) -> Result<ParseResult, ()> {
-    args.next();
+fn parse_globals(mut args: args::Args) -> Result<Globals, ArgError> {
+    Ok(Globals {
+        path: args.value(STORE_OPTION),
+        output: args.parse(FORMAT_OPTION, "text or json")?.unwrap_or(OutputFormat::Text),
+        interactive: !args.flag(NON_INTERACTIVE_OPTION),
+    })
+}
 
-    let mut args = args.collect::<Vec<String>>();
+/// The config in the user's config directory. A config left next to the program
+/// by earlier versions is moved there first.
+fn config_path() -> Result<String, Error> {
+    let legacy_path = exe_dir().join(CONFIG_FILENAME);
 
-    if args.len() < 1 { return Err(()); }
+    let path = match Config::default_path() {
+        Some(path) => path,
+        None => return Ok(path_str(&legacy_path)),
+    };
 
-    let mut dir = env::current_exe().unwrap();
-    dir.pop();
-    dir.push(FILENAME);
-    let path = String::from(dir.to_str().unwrap());
+    if !path.exists() && legacy_path.exists() {
+        move_config(&legacy_path, &path)?;
+        eprintln!("Moved the config from \"{}\" to \"{}\"", legacy_path.display(), path.display());
+    }
+
+    Ok(path_str(&path))
+}
 
+fn is_config_command(cmd: &str) -> bool {
+    resolve_command(cmd).is_ok_and(|info| info.name == CONFIG_COMMAND)
+}
+
+fn move_config(from: &Path, to: &Path) -> Result<(), Error> {
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir).map_err(|err| Error::write(&path_str(to), err))?;
+    }
+
+    // Copied rather than renamed, the config directory may be on another file system.
+    fs::copy(from, to).map_err(|err| Error::write(&path_str(to), err))?;
+    fs::remove_file(from).map_err(|err| Error::write(&path_str(from), err))
+}
+
+/// Replaces a user-defined alias by its command and arguments. Built-in commands can't be
+/// redefined, and aliases aren't expanded again.
+fn expand_alias(config: &Config, mut args: Vec<String>) -> (String, Vec<String>) {
     let cmd = args.remove(0);
 
-    Ok(ParseResult {
-        cmd,
-        args,
-        path,
-    })
+    match (resolve_command(&cmd), config.aliases.get(&cmd)) {
+        (Err(_), Some(alias)) => {
+            let mut expanded = alias.split_whitespace().map(String::from).collect::<Vec<String>>();
+            if expanded.is_empty() { return (cmd, args) }
+
+            let cmd = expanded.remove(0);
+            expanded.extend(args);
+            (cmd, expanded)
+        },
+        _ => (cmd, args),
+    }
+}
+
+fn exe_dir() -> PathBuf {
+    let mut dir = env::current_exe().unwrap();
+    dir.pop();
+    dir
+}
+
+fn path_str(path: &Path) -> String {
+    String::from(path.to_str().unwrap())
 }
 
-fn help() {
-    println!("Usage: pass <command> [args]");
+fn help(config: &Config) {
+    println!("Usage: pass {} <command> [args]", args::usage(&GLOBAL_SPEC));
+    println!("Global options:");
+    args::describe(&GLOBAL_SPEC).iter().for_each(|(name, help)| println!("  {:24} {}", name, help));
     println!("Supported commands:");
-    println!("  {:10} - add new password", CMD_ADD);
-    println!("  {:10} - add new password with the clipboard content", CMD_PASTE);
-    println!("  {:10} - remove password", CMD_REMOVE);
-    println!("  {:10} - update password", CMD_UPDATE);
-    println!("  {:10} - show all keys", CMD_LIST);
-    println!("  {:10} - show password by key", CMD_SHOW);
-    println!("  {:10} - encrypt passwords using passphrase and export", CMD_EXPORT);
-    println!("  {:10} - import passwords and decrypt using passphrase", CMD_IMPORT);
-    println!("  {:10} - rename specified key", CMD_RENAME);
-    println!("  {:10} - clear password list", CMD_CLEAR);
-    println!("  {:10} - copy password to clipboard", CMD_COPY);
-    println!("  {:10} - add one password for multiple keys", CMD_MULTIADD);
-    println!("  {:10} - remove multiple passwords", CMD_MULTIREMOVE);
-    println!("  {:10} - update multiple passwords with one value", CMD_MULTIUPDATE);
+    COMMANDS.iter().for_each(|c| match c.aliases.is_empty() {
+        true => println!("  {:10} - {}", c.name, c.summary),
+        false => println!("  {:10} - {} (also {})", c.name, c.summary, c.aliases.join(", ")),
+    });
+    if !config.aliases.is_empty() {
+        println!("User aliases:");
+        config.aliases.iter().for_each(|(name, command)| println!("  {:10} - {}", name, command));
+    }
+    let plugins = plugin_names();
+    if !plugins.is_empty() {
+        println!("Plugins: {}", plugins.join(", "));
+    }
+    println!("Exit codes: 0 - success, 10 - usage, 11 - no such key, 12 - already exists,");
+    println!("  13 - bad input, 14 - I/O error, 15 - invalid file, 16 - encryption or decryption failed, 17 - git failed,");
+    println!("  18 - plugin failed");
+    println!("Run \"pass <command> --help\" for the arguments of a command");
+}
+
+fn command_help(info: &CommandInfo) {
+    println!("{}", info.summary);
+    println!("Usage: pass {}", info.usage());
+    if !info.aliases.is_empty() {
+        println!("Aliases: {}", info.aliases.join(", "));
+    }
+    args::describe(&info.builder.spec()).iter().for_each(|(name, help)| println!("  {:24} {}", name, help));
 }
 
-fn command_usage(cmd_name: &str, cmd: Box<dyn CmdBuilder>) {
-    println!("Usage for \"{}\": {} {}", cmd_name, cmd_name, cmd.cmd_usage());
+fn usage_error(err: &ArgError, invocation: &str, spec: &Spec) -> ! {
+    eprintln!("Error: {}", err);
+    eprintln!("Usage: {} {}", invocation, args::usage(spec));
+    std::process::exit(EXIT_USAGE);
 }
 
 fn unknown_command(cmd: &str) {
@@ -0,0 +1,34 @@
+use crate::error::{Error, InputError};
+use crate::merger::{Choice, ConflictResolver, Resolution};
+use crate::secret::SecretString;
+
+/// Questions an operation may need to ask the user.
+pub trait Prompt {
+    /// Asks a yes/no question.
+    fn confirm(&mut self, message: &str) -> Result<bool, Error>;
+    /// Asks for a password, typed twice when confirm is set.
+    fn password(&mut self, confirm: bool) -> Result<SecretString, Error>;
+}
+
+/// Answers nothing: every question fails, so an operation that needs input stops instead of guessing.
+pub struct NonInteractive;
+
+impl Prompt for NonInteractive {
+    fn confirm(&mut self, _message: &str) -> Result<bool, Error> {
+        Err(Error::Input(InputError::NotInteractive))
+    }
+
+    fn password(&mut self, _confirm: bool) -> Result<SecretString, Error> {
+        Err(Error::Input(InputError::NotInteractive))
+    }
+}
+
+impl ConflictResolver for NonInteractive {
+    fn resolve(&mut self, _collisions: &[String]) -> Result<Resolution, Error> {
+        Err(Error::Input(InputError::NotInteractive))
+    }
+
+    fn choose(&mut self, _key: &str) -> Result<Choice, Error> {
+        Err(Error::Input(InputError::NotInteractive))
+    }
+}
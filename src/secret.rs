@@ -0,0 +1,101 @@
+use std::fmt;
+
+use zeroize::Zeroize;
+
+const REDACTED: &str = "[redacted]";
+
+/// A password or any other secret text. The memory is wiped when it's dropped,
+/// and the value is never printed by accident: Debug and Display show a placeholder,
+/// so the text has to be taken out explicitly with expose().
+pub struct SecretString(String);
+
+impl SecretString {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(s: String) -> SecretString {
+        memory::lock(s.as_ptr(), s.capacity());
+        SecretString(s)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(s: &str) -> SecretString {
+        SecretString::from(String::from(s))
+    }
+}
+
+impl Clone for SecretString {
+    fn clone(&self) -> SecretString {
+        SecretString::from(self.expose())
+    }
+}
+
+impl PartialEq for SecretString {
+    fn eq(&self, other: &SecretString) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for SecretString {}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.0.zeroize();
+        memory::unlock(self.0.as_ptr(), self.0.capacity());
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl fmt::Display for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Keeps secrets out of core dumps. Best effort, failures are ignored.
+pub fn harden_process() {
+    memory::disable_core_dumps();
+}
+
+#[cfg(unix)]
+mod memory {
+    // Locking is page granular and locks don't nest, so unlocking one secret may unlock
+    // a page shared with another. Swapping them out is unlikely, not impossible.
+    pub fn lock(ptr: *const u8, len: usize) {
+        if len > 0 {
+            unsafe { libc::mlock(ptr as *const libc::c_void, len) };
+        }
+    }
+
+    pub fn unlock(ptr: *const u8, len: usize) {
+        if len > 0 {
+            unsafe { libc::munlock(ptr as *const libc::c_void, len) };
+        }
+    }
+
+    pub fn disable_core_dumps() {
+        let limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) };
+
+        #[cfg(target_os = "linux")]
+        unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0) };
+    }
+}
+
+#[cfg(not(unix))]
+mod memory {
+    pub fn lock(_ptr: *const u8, _len: usize) {}
+
+    pub fn unlock(_ptr: *const u8, _len: usize) {}
+
+    pub fn disable_core_dumps() {}
+}
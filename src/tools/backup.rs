@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::config::BackupConfig;
+use crate::context::{self, SECONDS_IN_DAY};
+use crate::error::Error;
+
+const BACKUPS_SUFFIX: &str = ".backups";
+const BACKUP_EXTENSION: &str = ".data";
+const TIMESTAMP_LEN: usize = 15;
+const DAYS_IN_WEEK: u64 = 7;
+const DEFAULT_KEEP: usize = 10;
+const DEFAULT_DAILY: usize = 7;
+const DEFAULT_WEEKLY: usize = 4;
+
+/// A copy of the store as it was before a change, in "<store>.backups" next to it. Backups are
+/// copies of the store file as it is, so they are protected the way the store is.
+pub struct Backup {
+    /// When it was taken, like "20261019-100412" in UTC.
+    pub id: String,
+    pub path: PathBuf,
+    /// Seconds since the unix epoch.
+    pub time: u64,
+}
+
+impl Backup {
+    /// The time of the id, like "2026-10-19 10:04:12".
+    pub fn date(&self) -> String {
+        let id = &self.id;
+        format!("{}-{}-{} {}:{}:{}", &id[0..4], &id[4..6], &id[6..8], &id[9..11], &id[11..13], &id[13..15])
+    }
+}
+
+pub fn dir(store_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}{}", store_path, BACKUPS_SUFFIX))
+}
+
+/// Copies the store file before it's overwritten and drops the backups no longer kept.
+pub fn create(store_path: &str, config: &BackupConfig) -> Result<(), Error> {
+    if keep(config) == 0 && daily(config) == 0 && weekly(config) == 0 { return Ok(()) }
+
+    let dir = dir(store_path);
+    let dir_str = dir.to_string_lossy().into_owned();
+    fs::create_dir_all(&dir).map_err(|err| Error::write(&dir_str, err))?;
+
+    let id = timestamp(context::now());
+    let mut path = dir.join(format!("{}{}", id, BACKUP_EXTENSION));
+    // More than one write a second, the later one gets a number.
+    let mut n = 2;
+    while path.exists() {
+        path = dir.join(format!("{}-{}{}", id, n, BACKUP_EXTENSION));
+        n += 1;
+    }
+
+    fs::copy(store_path, &path).map_err(|err| Error::write(&path.to_string_lossy(), err))?;
+
+    prune(list(store_path)?, config)
+}
+
+/// All the backups, newest first.
+pub fn list(store_path: &str) -> Result<Vec<Backup>, Error> {
+    let dir = dir(store_path);
+
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(Error::read(&dir.to_string_lossy(), err)),
+    };
+
+    let mut backups = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let id = name.strip_suffix(BACKUP_EXTENSION).filter(|id| is_id(id))?;
+            let time = entry.metadata().ok()?.modified().ok()?
+                .duration_since(std::time::UNIX_EPOCH).ok()?.as_secs();
+
+            Some(Backup { id: String::from(id), path: entry.path(), time })
+        })
+        .collect::<Vec<Backup>>();
+
+    backups.sort_by(|a, b| order(&b.id).cmp(&order(&a.id)));
+    Ok(backups)
+}
+
+pub fn find(store_path: &str, id: &str) -> Result<Backup, Error> {
+    list(store_path)?.into_iter()
+        .find(|b| b.id == id)
+        .ok_or_else(|| Error::NotFound(String::from(id)))
+}
+
+/// Keeps the newest backups, and the newest one of each of the last days and weeks
+/// having backups.
+fn prune(backups: Vec<Backup>, config: &BackupConfig) -> Result<(), Error> {
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+
+    for (i, backup) in backups.iter().enumerate() {
+        let day = backup.time / SECONDS_IN_DAY;
+        let week = day / DAYS_IN_WEEK;
+
+        let kept_daily = days.len() < daily(config) && days.insert(day);
+        let kept_weekly = weeks.len() < weekly(config) && weeks.insert(week);
+
+        if i >= keep(config) && !kept_daily && !kept_weekly {
+            fs::remove_file(&backup.path).map_err(|err| Error::write(&backup.path.to_string_lossy(), err))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn keep(config: &BackupConfig) -> usize {
+    config.keep.unwrap_or(DEFAULT_KEEP)
+}
+
+fn daily(config: &BackupConfig) -> usize {
+    config.daily.unwrap_or(DEFAULT_DAILY)
+}
+
+fn weekly(config: &BackupConfig) -> usize {
+    config.weekly.unwrap_or(DEFAULT_WEEKLY)
+}
+
+/// The time of an id, then its number within the same second.
+fn order(id: &str) -> (&str, u32) {
+    let (time, n) = id.split_at(TIMESTAMP_LEN);
+    (time, n.trim_start_matches('-').parse().unwrap_or(1))
+}
+
+/// "YYYYMMDD-HHMMSS", optionally followed by "-<n>".
+fn is_id(id: &str) -> bool {
+    let bytes = id.as_bytes();
+    bytes.len() >= TIMESTAMP_LEN
+        && bytes[..8].iter().chain(&bytes[9..TIMESTAMP_LEN]).all(u8::is_ascii_digit)
+        && bytes[8] == b'-'
+        && match &bytes[TIMESTAMP_LEN..] {
+            [] => true,
+            [b'-', n @ ..] => !n.is_empty() && n.iter().all(u8::is_ascii_digit),
+            _ => false,
+        }
+}
+
+fn timestamp(secs: u64) -> String {
+    let (year, month, day) = civil_from_days((secs / SECONDS_IN_DAY) as i64);
+    let rest = secs % SECONDS_IN_DAY;
+
+    format!("{:04}{:02}{:02}-{:02}{:02}{:02}", year, month, day, rest / 3600, rest / 60 % 60, rest % 60)
+}
+
+// The calendar date of days since 1970-01-01, by Howard Hinnant's algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+
+    (yoe + era * 400 + if month <= 2 { 1 } else { 0 }, month, day)
+}
+
@@ -0,0 +1,76 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+
+use sha1::{Digest, Sha1};
+
+const HASH_LEN: usize = 40;
+
+/// Looks a password up in a Have I Been Pwned "SHA-1 ordered by hash" file
+/// and returns how many times it was seen in breaches.
+/// The file is searched in place, only a few lines are read per lookup.
+pub struct BreachIndex {
+    reader: BufReader<File>,
+    len: u64,
+}
+
+impl BreachIndex {
+    pub fn open(path: &str) -> Result<BreachIndex, io::Error> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+
+        Ok(BreachIndex { reader: BufReader::new(file), len })
+    }
+
+    pub fn lookup(&mut self, password: &str) -> Result<Option<u64>, io::Error> {
+        let hash = Sha1::digest(password.as_bytes()).iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<String>();
+
+        // a line matching the hash can only start within [lo, hi)
+        let (mut lo, mut hi) = (0, self.len);
+
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let start = self.line_start_from(mid)?;
+
+            if start >= hi {
+                hi = mid;
+                continue;
+            }
+
+            let mut line = String::new();
+            let read = self.reader.read_line(&mut line)? as u64;
+            let line = line.trim_end();
+
+            let line_hash = match line.get(..HASH_LEN) {
+                Some(h) => h.to_uppercase(),
+                None => return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected line format")),
+            };
+
+            match line_hash.cmp(&hash) {
+                std::cmp::Ordering::Equal => {
+                    let count = line[HASH_LEN..].trim_start_matches(':').parse().unwrap_or(1);
+                    return Ok(Some(count));
+                },
+                std::cmp::Ordering::Less => lo = start + read,
+                std::cmp::Ordering::Greater => hi = mid,
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Positions the reader at the first line starting at or after pos.
+    fn line_start_from(&mut self, pos: u64) -> Result<u64, io::Error> {
+        if pos == 0 {
+            self.reader.seek(SeekFrom::Start(0))?;
+            return Ok(0);
+        }
+
+        self.reader.seek(SeekFrom::Start(pos - 1))?;
+        let mut skipped = vec![];
+        let read = self.reader.read_until(b'\n', &mut skipped)? as u64;
+
+        Ok(pos - 1 + read)
+    }
+}
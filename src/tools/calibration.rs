@@ -0,0 +1,68 @@
+use std::time::{Duration, Instant};
+
+use crate::error::CryptoError;
+
+use super::encryption::{KdfParams, TindercryptCryptor, MAX_KDF_ITERATIONS};
+
+const MIN_MEMORY_KIB: u32 = 8 * 1024;
+const MAX_PARALLELISM: u32 = 4;
+const PBKDF2_PROBE_ITERATIONS: u32 = 10_000;
+const PBKDF2_ROUNDING: u32 = 1000;
+const PROBE_SECRET: &[u8] = b"calibration";
+const PROBE_SALT: &[u8] = &[0; 16];
+
+/// Picks Argon2id parameters taking about the target time on this machine.
+/// Memory starts at the default and is halved while a single pass is slower than the target.
+pub fn calibrate_argon2(target: Duration) -> Result<KdfParams, CryptoError> {
+    let parallelism = std::thread::available_parallelism()
+        .map(|n| (n.get() as u32).min(MAX_PARALLELISM))
+        .unwrap_or(1);
+
+    let mut params = KdfParams { iterations: 1, parallelism, ..KdfParams::default() };
+    let mut elapsed = measure(|| params.derive_key(PROBE_SECRET, PROBE_SALT).map(|_| ()))?;
+
+    while elapsed > target && params.memory_kib / 2 >= MIN_MEMORY_KIB {
+        params.memory_kib /= 2;
+        elapsed = measure(|| params.derive_key(PROBE_SECRET, PROBE_SALT).map(|_| ()))?;
+    }
+
+    params.iterations = scale(1, elapsed, target).clamp(1, MAX_KDF_ITERATIONS);
+    Ok(params)
+}
+
+/// Picks the PBKDF2 iteration count used by the legacy format.
+pub fn calibrate_pbkdf2(target: Duration) -> Result<u32, CryptoError> {
+    let probe = TindercryptCryptor { iterations: PBKDF2_PROBE_ITERATIONS };
+    let elapsed = measure(|| probe.derive_key(PROBE_SECRET))?;
+
+    let iterations = scale(PBKDF2_PROBE_ITERATIONS, elapsed, target) / PBKDF2_ROUNDING * PBKDF2_ROUNDING;
+    Ok(iterations.max(PBKDF2_PROBE_ITERATIONS))
+}
+
+pub fn measure_argon2(params: KdfParams) -> Result<Duration, CryptoError> {
+    measure(|| params.derive_key(PROBE_SECRET, PROBE_SALT).map(|_| ()))
+}
+
+fn measure<F: Fn() -> Result<(), CryptoError>>(f: F) -> Result<Duration, CryptoError> {
+    let start = Instant::now();
+    f()?;
+    Ok(start.elapsed())
+}
+
+fn scale(count: u32, elapsed: Duration, target: Duration) -> u32 {
+    let ratio = target.as_secs_f64() / elapsed.as_secs_f64().max(f64::EPSILON);
+    (count as f64 * ratio).min(u32::MAX as f64) as u32
+}
+
+/// Parses durations like "1s", "500ms" or "1.5" (seconds).
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let (number, scale) = match s.strip_suffix("ms") {
+        Some(ms) => (ms, 0.001),
+        None => (s.strip_suffix('s').unwrap_or(s), 1.0),
+    };
+
+    match number.parse::<f64>() {
+        Ok(n) if n > 0.0 && n.is_finite() => Some(Duration::from_secs_f64(n * scale)),
+        _ => None,
+    }
+}
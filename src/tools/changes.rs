@@ -0,0 +1,82 @@
+use crate::context::{Context, PassListModel};
+
+/// Keys that differ between two states of a store. Only key names are kept, never values.
+#[derive(Default)]
+pub struct Changes {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Value, max age or tags changed.
+    pub changed: Vec<String>,
+    pub settings: bool,
+}
+
+/// What a store looked like, to find out what a command changed.
+pub struct Snapshot {
+    model: PassListModel,
+    max_age: Option<u64>,
+}
+
+impl Snapshot {
+    pub fn of(context: &Context) -> Snapshot {
+        Snapshot { model: context.model.clone(), max_age: context.settings.max_age }
+    }
+
+    pub fn changes(&self, context: &Context) -> Changes {
+        let mut changes = between(&self.model, &context.model);
+        changes.settings = self.max_age != context.settings.max_age;
+        changes
+    }
+}
+
+/// Sorted keys added, removed and changed from old to new.
+pub fn between(old: &PassListModel, new: &PassListModel) -> Changes {
+    let mut changes = Changes::default();
+
+    for (key, entry) in new {
+        match old.get(key) {
+            None => changes.added.push(key.clone()),
+            Some(o) if o.value != entry.value || o.max_age != entry.max_age || o.tags != entry.tags => changes.changed.push(key.clone()),
+            Some(_) => (),
+        }
+    }
+
+    changes.removed = old.keys().filter(|key| !new.contains_key(*key)).cloned().collect();
+
+    changes.added.sort();
+    changes.removed.sort();
+    changes.changed.sort();
+    changes
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty() && !self.settings
+    }
+
+    /// One line per kind of change, like "Added mail, bank".
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = vec![];
+
+        for (verb, keys) in [("Added", &self.added), ("Removed", &self.removed), ("Changed", &self.changed)] {
+            if !keys.is_empty() {
+                lines.push(format!("{} {}", verb, keys.join(", ")));
+            }
+        }
+
+        if self.settings {
+            lines.push(String::from("Changed the settings"));
+        }
+
+        lines
+    }
+
+    /// A commit message: the change itself, or a summary followed by every change.
+    pub fn message(&self) -> String {
+        let lines = self.describe();
+
+        match lines.len() {
+            1 => lines[0].clone(),
+            _ => format!("Update {} entries\n\n{}", self.added.len() + self.removed.len() + self.changed.len(), lines.join("\n")),
+        }
+    }
+}
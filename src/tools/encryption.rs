@@ -0,0 +1,439 @@
+use std::marker::PhantomData;
+
+use aes_gcm::Aes256Gcm;
+use argon2::{Algorithm as Argon2Algorithm, Argon2, Params, Version};
+use chacha20poly1305::XChaCha20Poly1305;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, Payload, generic_array::{GenericArray, typenum::Unsigned}};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use tindercrypt::cryptors::RingCryptor;
+use tindercrypt::metadata::{
+    EncryptionAlgorithm, EncryptionMetadata, KeyDerivationAlgorithm, KeyDerivationMetadata, Metadata,
+    PBKDF2_DEFAULT_ITERATIONS,
+};
+use zeroize::Zeroizing;
+
+use crate::config::KdfConfig;
+use crate::error::CryptoError;
+
+// Exports start with a small header, files without it are
+// bare tindercrypt containers written by earlier versions.
+//
+// v1: magic | version | flags                 (tindercrypt body)
+// v2: magic | version | flags | algorithm     (body depends on the algorithm)
+//
+// For the AEAD algorithms the body is
+// memory_kib | iterations | parallelism | salt | nonce | ciphertext,
+// and everything before the ciphertext is authenticated.
+const MAGIC: &[u8; 4] = b"PASS";
+const VERSION_KEY_FILE: u8 = 1;
+const VERSION_ALGORITHMS: u8 = 2;
+const HEADER_LEN: usize = MAGIC.len() + 3;
+
+const FLAG_KEY_FILE: u8 = 0b0000_0001;
+
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+const KDF_PARAMS_LEN: usize = 12;
+
+// Upper bounds of the Argon2id cost, the parameters of a file are checked before deriving
+// the key so a crafted one can't make us allocate terabytes or run for days.
+pub const MAX_KDF_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+pub const MAX_KDF_ITERATIONS: u32 = 64;
+pub const MAX_KDF_PARALLELISM: u32 = 16;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    Tindercrypt,
+    XChaCha20Poly1305,
+    Aes256Gcm,
+}
+
+impl Algorithm {
+    pub fn from_name(name: &str) -> Option<Algorithm> {
+        match name {
+            "legacy" => Some(Algorithm::Tindercrypt),
+            "xchacha20" => Some(Algorithm::XChaCha20Poly1305),
+            "aes256gcm" => Some(Algorithm::Aes256Gcm),
+            _ => None,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Algorithm, CryptoError> {
+        match id {
+            0 => Ok(Algorithm::Tindercrypt),
+            1 => Ok(Algorithm::XChaCha20Poly1305),
+            2 => Ok(Algorithm::Aes256Gcm),
+            _ => Err(CryptoError::Format),
+        }
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            Algorithm::Tindercrypt => 0,
+            Algorithm::XChaCha20Poly1305 => 1,
+            Algorithm::Aes256Gcm => 2,
+        }
+    }
+}
+
+/// Argon2id cost parameters.
+#[derive(Clone, Copy)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> KdfParams {
+        KdfParams { memory_kib: 64 * 1024, iterations: 3, parallelism: 4 }
+    }
+}
+
+impl KdfParams {
+    pub fn from_config(config: &KdfConfig) -> KdfParams {
+        let default = KdfParams::default();
+
+        KdfParams {
+            memory_kib: config.memory_kib.unwrap_or(default.memory_kib),
+            iterations: config.iterations.unwrap_or(default.iterations),
+            parallelism: config.parallelism.unwrap_or(default.parallelism),
+        }
+    }
+
+    pub fn derive_key(&self, secret: &[u8], salt: &[u8]) -> Result<Zeroizing<Vec<u8>>, CryptoError> {
+        if !self.within_limits() {
+            return Err(CryptoError::KeyDerivation);
+        }
+
+        let params = match Params::new(self.memory_kib, self.iterations, self.parallelism, Some(KEY_LEN)) {
+            Ok(p) => p,
+            Err(_) => return Err(CryptoError::KeyDerivation),
+        };
+
+        let mut key = Zeroizing::new(vec![0; KEY_LEN]);
+        match Argon2::new(Argon2Algorithm::Argon2id, Version::V0x13, params).hash_password_into(secret, salt, &mut key) {
+            Ok(_) => Ok(key),
+            Err(_) => Err(CryptoError::KeyDerivation),
+        }
+    }
+
+    pub fn within_limits(&self) -> bool {
+        self.memory_kib <= MAX_KDF_MEMORY_KIB && self.iterations <= MAX_KDF_ITERATIONS && self.parallelism <= MAX_KDF_PARALLELISM
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        [self.memory_kib, self.iterations, self.parallelism].iter()
+            .flat_map(|v| v.to_le_bytes().to_vec())
+            .collect()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<KdfParams, CryptoError> {
+        if bytes.len() < KDF_PARAMS_LEN {
+            return Err(CryptoError::Format);
+        }
+
+        let value = |idx: usize| u32::from_le_bytes([bytes[idx], bytes[idx + 1], bytes[idx + 2], bytes[idx + 3]]);
+        let params = KdfParams { memory_kib: value(0), iterations: value(4), parallelism: value(8) };
+
+        match params.within_limits() {
+            true => Ok(params),
+            false => Err(CryptoError::Format),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct EncryptionParams {
+    pub algorithm: Algorithm,
+    pub kdf: KdfParams,
+    /// Used by the legacy format only.
+    pub pbkdf2_iterations: u32,
+}
+
+/// Encryption settings given on the command line, the rest comes from the config.
+#[derive(Default)]
+pub struct EncryptionOptions {
+    pub algorithm: Option<Algorithm>,
+    pub memory_kib: Option<u32>,
+    pub iterations: Option<u32>,
+    pub parallelism: Option<u32>,
+}
+
+impl EncryptionOptions {
+    pub fn resolve(&self, config: &KdfConfig) -> EncryptionParams {
+        let kdf = KdfParams::from_config(config);
+
+        EncryptionParams {
+            algorithm: self.algorithm.unwrap_or(Algorithm::XChaCha20Poly1305),
+            kdf: KdfParams {
+                memory_kib: self.memory_kib.unwrap_or(kdf.memory_kib),
+                iterations: self.iterations.unwrap_or(kdf.iterations),
+                parallelism: self.parallelism.unwrap_or(kdf.parallelism),
+            },
+            pbkdf2_iterations: config.pbkdf2_iterations.unwrap_or(PBKDF2_DEFAULT_ITERATIONS as u32),
+        }
+    }
+}
+
+pub struct Header {
+    pub key_file_required: bool,
+    pub algorithm: Algorithm,
+}
+
+/// Encryption backend. The header is passed in to be authenticated along with the data
+/// by the backends supporting it, the sealed body doesn't include it.
+pub trait Cryptor {
+    fn seal(&self, secret: &[u8], header: &[u8], data: &[u8]) -> Result<Vec<u8>, CryptoError>;
+    fn open(&self, secret: &[u8], header: &[u8], body: &[u8]) -> Result<Vec<u8>, CryptoError>;
+}
+
+pub fn cryptor(params: EncryptionParams) -> Box<dyn Cryptor> {
+    match params.algorithm {
+        Algorithm::Tindercrypt => Box::new(TindercryptCryptor { iterations: params.pbkdf2_iterations }),
+        Algorithm::XChaCha20Poly1305 => Box::new(AeadCryptor::<XChaCha20Poly1305>::new(params.kdf)),
+        Algorithm::Aes256Gcm => Box::new(AeadCryptor::<Aes256Gcm>::new(params.kdf)),
+    }
+}
+
+/// Whether the data starts with the header of our exports, the legacy ones have none.
+pub fn has_header(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub fn read_header(encrypted: &[u8]) -> Result<Header, CryptoError> {
+    let (header, _body) = split_header(encrypted)?;
+    Ok(header)
+}
+
+pub fn encrypt(orig_data: &[u8], pass: &str, key_file: Option<&[u8]>, params: EncryptionParams) -> Result<Vec<u8>, CryptoError> {
+    let flags = if key_file.is_some() { FLAG_KEY_FILE } else { 0 };
+
+    let mut result = MAGIC.to_vec();
+    result.push(VERSION_ALGORITHMS);
+    result.push(flags);
+    result.push(params.algorithm.id());
+
+    let body = cryptor(params).seal(&passphrase(pass, key_file), &result, orig_data)?;
+    result.extend_from_slice(&body);
+
+    Ok(result)
+}
+
+/// The decrypted data is wiped when dropped.
+pub fn decrypt(encrypted: &[u8], pass: &str, key_file: Option<&[u8]>) -> Result<Zeroizing<Vec<u8>>, CryptoError> {
+    let (header, body) = split_header(encrypted)?;
+    let header_bytes = &encrypted[..encrypted.len() - body.len()];
+
+    // the cost parameters for opening are taken from the file itself
+    let params = EncryptionOptions { algorithm: Some(header.algorithm), ..EncryptionOptions::default() }
+        .resolve(&KdfConfig::default());
+
+    cryptor(params).open(&passphrase(pass, key_file), header_bytes, body).map(Zeroizing::new)
+}
+
+/// Hash of a key file as used in the composite key, the same way KeePass treats arbitrary files.
+pub fn key_file_hash(key_file: &[u8]) -> Zeroizing<Vec<u8>> {
+    Zeroizing::new(Sha256::digest(key_file).to_vec())
+}
+
+fn split_header(encrypted: &[u8]) -> Result<(Header, &[u8]), CryptoError> {
+    if !encrypted.starts_with(MAGIC) {
+        return Ok((Header { key_file_required: false, algorithm: Algorithm::Tindercrypt }, encrypted));
+    }
+
+    match encrypted.get(MAGIC.len()..HEADER_LEN) {
+        Some([VERSION_KEY_FILE, flags, _]) => Ok((
+            Header { key_file_required: flags & FLAG_KEY_FILE != 0, algorithm: Algorithm::Tindercrypt },
+            &encrypted[HEADER_LEN - 1..],
+        )),
+        Some([VERSION_ALGORITHMS, flags, algorithm]) => Ok((
+            Header { key_file_required: flags & FLAG_KEY_FILE != 0, algorithm: Algorithm::from_id(*algorithm)? },
+            &encrypted[HEADER_LEN..],
+        )),
+        _ => Err(CryptoError::Format),
+    }
+}
+
+fn passphrase(pass: &str, key_file: Option<&[u8]>) -> Zeroizing<Vec<u8>> {
+    let mut result = Zeroizing::new(Vec::with_capacity(pass.len() + 1 + KEY_LEN));
+    result.extend_from_slice(pass.as_bytes());
+
+    if let Some(key) = key_file {
+        result.push(0);
+        result.extend_from_slice(&key_file_hash(key));
+    }
+
+    result
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+/// The original format: tindercrypt's AES-256-GCM with PBKDF2-SHA256.
+pub struct TindercryptCryptor {
+    pub iterations: u32,
+}
+
+impl TindercryptCryptor {
+    fn metadata(&self, data_len: usize) -> Metadata {
+        let mut key_meta = KeyDerivationMetadata::generate();
+        key_meta.iterations = self.iterations as usize;
+
+        Metadata::new(
+            KeyDerivationAlgorithm::PBKDF2(key_meta),
+            EncryptionAlgorithm::AES256GCM(EncryptionMetadata::generate()),
+            data_len,
+        )
+    }
+
+    /// Runs the key derivation alone, used to measure its cost.
+    pub fn derive_key(&self, secret: &[u8]) -> Result<(), CryptoError> {
+        RingCryptor::derive_key(&self.metadata(0), secret).map(|_| ()).map_err(|_| CryptoError::KeyDerivation)
+    }
+}
+
+impl Cryptor for TindercryptCryptor {
+    fn seal(&self, secret: &[u8], _header: &[u8], data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let meta = self.metadata(data.len());
+        let key = RingCryptor::derive_key(&meta, secret).map_err(|_| CryptoError::KeyDerivation)?;
+        RingCryptor::new().seal_with_meta(&meta, &key, data).map_err(|_| CryptoError::Encrypt)
+    }
+
+    fn open(&self, secret: &[u8], _header: &[u8], body: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        RingCryptor::new().open(secret, body).map_err(|_| CryptoError::Decrypt)
+    }
+}
+
+struct AeadCryptor<C> {
+    kdf: KdfParams,
+    cipher: PhantomData<C>,
+}
+
+impl<C> AeadCryptor<C> {
+    fn new(kdf: KdfParams) -> AeadCryptor<C> {
+        AeadCryptor { kdf, cipher: PhantomData }
+    }
+}
+
+impl<C: KeyInit + Aead + AeadCore> Cryptor for AeadCryptor<C> {
+    fn seal(&self, secret: &[u8], header: &[u8], data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let salt = random_bytes(SALT_LEN);
+        let nonce = random_bytes(C::NonceSize::USIZE);
+
+        let mut body = self.kdf.to_bytes();
+        body.extend_from_slice(&salt);
+        body.extend_from_slice(&nonce);
+
+        let key = self.kdf.derive_key(secret, &salt)?;
+        let cipher = C::new_from_slice(&key).map_err(|_| CryptoError::Encrypt)?;
+
+        let aad = [header, &body].concat();
+        let encrypted = cipher.encrypt(GenericArray::from_slice(&nonce), Payload { msg: data, aad: &aad }).map_err(|_| CryptoError::Encrypt)?;
+
+        body.extend_from_slice(&encrypted);
+        Ok(body)
+    }
+
+    fn open(&self, secret: &[u8], header: &[u8], body: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let nonce_start = KDF_PARAMS_LEN + SALT_LEN;
+        let data_start = nonce_start + C::NonceSize::USIZE;
+
+        if body.len() < data_start {
+            return Err(CryptoError::Format);
+        }
+
+        let kdf = KdfParams::from_bytes(&body[..KDF_PARAMS_LEN])?;
+        let key = kdf.derive_key(secret, &body[KDF_PARAMS_LEN..nonce_start])?;
+        let cipher = C::new_from_slice(&key).map_err(|_| CryptoError::Decrypt)?;
+
+        let aad = [header, &body[..data_start]].concat();
+        let nonce = GenericArray::from_slice(&body[nonce_start..data_start]);
+
+        cipher.decrypt(nonce, Payload { msg: &body[data_start..], aad: &aad }).map_err(|_| CryptoError::Decrypt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMALL_KDF: KdfParams = KdfParams { memory_kib: 8 * 1024, iterations: 1, parallelism: 1 };
+
+    fn params(algorithm: Algorithm) -> EncryptionParams {
+        EncryptionParams { algorithm, kdf: SMALL_KDF, pbkdf2_iterations: 1000 }
+    }
+
+    #[test]
+    fn round_trip() {
+        for &algorithm in &[Algorithm::Tindercrypt, Algorithm::XChaCha20Poly1305, Algorithm::Aes256Gcm] {
+            for &key_file in &[None, Some(&b"key file"[..])] {
+                let encrypted = encrypt(b"secret data", "pass", key_file, params(algorithm)).unwrap();
+
+                let header = read_header(&encrypted).unwrap();
+                assert!(header.algorithm == algorithm);
+                assert_eq!(header.key_file_required, key_file.is_some());
+
+                assert_eq!(&decrypt(&encrypted, "pass", key_file).unwrap()[..], b"secret data");
+            }
+        }
+    }
+
+    #[test]
+    fn wrong_secret() {
+        let encrypted = encrypt(b"secret data", "pass", Some(b"key file"), params(Algorithm::XChaCha20Poly1305)).unwrap();
+
+        assert!(matches!(decrypt(&encrypted, "other", Some(b"key file")), Err(CryptoError::Decrypt)));
+        assert!(matches!(decrypt(&encrypted, "pass", Some(b"other")), Err(CryptoError::Decrypt)));
+        assert!(matches!(decrypt(&encrypted, "pass", None), Err(CryptoError::Decrypt)));
+    }
+
+    #[test]
+    fn authenticated_header() {
+        let mut encrypted = encrypt(b"secret data", "pass", None, params(Algorithm::Aes256Gcm)).unwrap();
+        // the key file flag isn't part of the key, only of the authenticated header
+        encrypted[MAGIC.len() + 1] |= FLAG_KEY_FILE;
+
+        assert!(matches!(decrypt(&encrypted, "pass", None), Err(CryptoError::Decrypt)));
+    }
+
+    #[test]
+    fn kdf_params_bytes() {
+        let bytes = SMALL_KDF.to_bytes();
+        assert_eq!(bytes.len(), KDF_PARAMS_LEN);
+
+        let params = KdfParams::from_bytes(&bytes).unwrap();
+        assert_eq!((params.memory_kib, params.iterations, params.parallelism), (8 * 1024, 1, 1));
+
+        assert!(matches!(KdfParams::from_bytes(&bytes[..KDF_PARAMS_LEN - 1]), Err(CryptoError::Format)));
+    }
+
+    #[test]
+    fn kdf_params_limits() {
+        let at_limits = KdfParams { memory_kib: MAX_KDF_MEMORY_KIB, iterations: MAX_KDF_ITERATIONS, parallelism: MAX_KDF_PARALLELISM };
+        assert!(KdfParams::from_bytes(&at_limits.to_bytes()).is_ok());
+
+        let over_limits = [
+            KdfParams { memory_kib: u32::MAX, ..SMALL_KDF },
+            KdfParams { iterations: MAX_KDF_ITERATIONS + 1, ..SMALL_KDF },
+            KdfParams { parallelism: MAX_KDF_PARALLELISM + 1, ..SMALL_KDF },
+        ];
+
+        for params in &over_limits {
+            assert!(matches!(KdfParams::from_bytes(&params.to_bytes()), Err(CryptoError::Format)));
+            assert!(matches!(params.derive_key(b"secret", &[0; SALT_LEN]), Err(CryptoError::KeyDerivation)));
+        }
+    }
+
+    #[test]
+    fn crafted_kdf_params() {
+        let mut encrypted = encrypt(b"secret data", "pass", None, params(Algorithm::XChaCha20Poly1305)).unwrap();
+        encrypted[HEADER_LEN..HEADER_LEN + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert!(matches!(decrypt(&encrypted, "pass", None), Err(CryptoError::Format)));
+    }
+}
@@ -0,0 +1,24 @@
+use rand::seq::SliceRandom;
+
+use crate::secret::SecretString;
+
+pub const DEFAULT_LENGTH: usize = 20;
+
+const LETTERS_AND_DIGITS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+const SYMBOLS: &[u8] = b"!#$%&()*+,-./:;<=>?@[]^_{|}~";
+
+/// A random password of letters and digits, and symbols when asked for.
+pub fn generate(length: usize, symbols: bool) -> SecretString {
+    let mut charset = LETTERS_AND_DIGITS.to_vec();
+    if symbols {
+        charset.extend_from_slice(SYMBOLS);
+    }
+
+    let mut rng = rand::thread_rng();
+
+    // Sized up front, so the text is never left behind in a reallocated buffer.
+    let mut password = String::with_capacity(length);
+    password.extend((0..length).map(|_| *charset.choose(&mut rng).unwrap() as char));
+
+    SecretString::from(password)
+}
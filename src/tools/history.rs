@@ -0,0 +1,343 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+use crate::config::Config;
+use crate::context::{self, Context, PassListModel, Settings};
+use crate::error::Error;
+use crate::tools::encryption::{Algorithm, EncryptionParams, KdfParams};
+
+const GIT: &str = "git";
+const BRANCH: &str = "main";
+const REMOTE: &str = "origin";
+const SNAPSHOT_FILE: &str = "store.pass";
+const GIT_DIR: &str = ".git";
+/// Kept inside .git, so it's never committed or pushed.
+const KEY_FILE: &str = ".git/pass-sync.key";
+const KEY_LEN: usize = 64;
+const HISTORY_SUFFIX: &str = ".history";
+const DEFAULT_AUTHOR: [&str; 4] = ["-c", "user.name=pass", "-c", "user.email=pass@localhost"];
+
+/// Git history of a store, next to it by default. The repository holds one encrypted snapshot
+/// of the store per commit. The snapshots are locked with a random sync key instead of a
+/// passphrase, so every change can be committed without asking; the key has to be copied to
+/// the other machines sharing the history.
+pub struct History {
+    dir: PathBuf,
+    key: Zeroizing<Vec<u8>>,
+}
+
+/// The store of the remote, and the one both sides last had in common unless their
+/// histories are unrelated, each with its settings.
+pub struct Fetched {
+    pub store: PassListModel,
+    pub settings: Settings,
+    pub base: Option<(PassListModel, Settings)>,
+}
+
+impl History {
+    /// Where the history of the store lives.
+    pub fn dir(store_path: &str, config: &Config) -> PathBuf {
+        match &config.git.repo {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(format!("{}{}", store_path, HISTORY_SUFFIX)),
+        }
+    }
+
+    /// The history of the store, None when it wasn't initialized.
+    pub fn open(store_path: &str, config: &Config) -> Result<Option<History>, Error> {
+        let dir = History::dir(store_path, config);
+        let key_path = dir.join(KEY_FILE);
+
+        if !key_path.exists() { return Ok(None) }
+
+        let key = fs::read(&key_path).map_err(|err| Error::read(&path_str(&key_path), err))?;
+        Ok(Some(History { dir, key: Zeroizing::new(key) }))
+    }
+
+    /// Creates the repository, with the given sync key or a new one, and lets start make the
+    /// first commit. The history of the remote is taken over by the first fetch and merge.
+    /// The key is written last, it marks the history as set up. When any step fails, what
+    /// was created is removed again, so the next init starts over.
+    pub fn init<F>(dir: &Path, remote: Option<&str>, key: Option<Zeroizing<Vec<u8>>>, start: F) -> Result<History, Error>
+        where F: FnOnce(&History) -> Result<(), Error> {
+        if dir.join(KEY_FILE).exists() {
+            return Err(Error::AlreadyExists(path_str(dir)));
+        }
+
+        let created_dir = !dir.exists();
+        let created_repo = !dir.join(GIT_DIR).exists();
+
+        fs::create_dir_all(dir).map_err(|err| Error::write(&path_str(dir), err))?;
+
+        let key = key.unwrap_or_else(|| {
+            let mut key = Zeroizing::new(vec![0; KEY_LEN]);
+            rand::thread_rng().fill_bytes(&mut key);
+            key
+        });
+
+        let history = History { dir: dir.to_path_buf(), key };
+
+        let result = history.create_repo(remote)
+            .and_then(|_| start(&history))
+            .and_then(|_| history.write_key());
+
+        if let Err(err) = result {
+            // A repository that was there before is left alone, with only our snapshot taken out.
+            let _ = match (created_dir, created_repo) {
+                (true, _) => fs::remove_dir_all(dir),
+                (false, true) => fs::remove_dir_all(dir.join(GIT_DIR)).and_then(|_| remove_if_exists(&dir.join(SNAPSHOT_FILE))),
+                (false, false) => Ok(()),
+            };
+            return Err(err);
+        }
+
+        Ok(history)
+    }
+
+    pub fn key_path(&self) -> PathBuf {
+        self.dir.join(KEY_FILE)
+    }
+
+    /// Commits the current state of the store.
+    pub fn commit(&self, context: &Context, message: &str) -> Result<(), Error> {
+        self.write_snapshot(context)?;
+        self.git(&["add", SNAPSHOT_FILE])?;
+        self.commit_staged(message)
+    }
+
+    /// Fetches the remote and returns its store when it has commits not merged yet.
+    pub fn fetch(&self) -> Result<Option<Fetched>, Error> {
+        self.git(&["fetch", "--quiet", REMOTE])?;
+
+        if !self.remote_exists()? || self.is_merged(&remote_branch())? {
+            return Ok(None);
+        }
+
+        let base = match self.git(&["merge-base", "HEAD", &remote_branch()]) {
+            Ok(output) => Some(self.snapshot(String::from_utf8_lossy(&output.stdout).trim())?),
+            Err(_) => None,
+        };
+
+        let (store, settings) = self.snapshot(&remote_branch())?;
+        Ok(Some(Fetched { store, settings, base }))
+    }
+
+    /// Records the store, merged by the caller, as the merge of the fetched remote.
+    /// Git's own merge is told to keep our side, the snapshot is then replaced by the merged store.
+    pub fn commit_merge(&self, context: &Context) -> Result<(), Error> {
+        if !self.has_commits()? {
+            self.git(&["reset", "--quiet", "--soft", &remote_branch()])?;
+            return self.commit(context, &format!("Merge {}", REMOTE));
+        }
+
+        let branch = remote_branch();
+        self.git_as_author(&["merge", "--quiet", "--no-ff", "--no-commit", "-s", "ours", "--allow-unrelated-histories", &branch])?;
+
+        let result = self.commit(context, &format!("Merge {}", REMOTE));
+        if result.is_err() {
+            let _ = self.git(&["merge", "--abort"]);
+        }
+        result
+    }
+
+    pub fn push(&self) -> Result<(), Error> {
+        self.git(&["push", "--quiet", REMOTE, BRANCH]).map(|_| ())
+    }
+
+    /// One line per commit, newest first.
+    pub fn log(&self) -> Result<String, Error> {
+        if !self.has_commits()? { return Ok(String::new()) }
+
+        let output = self.git(&["log", "--date=format:%Y-%m-%d %H:%M", "--format=%h %ad %s%w(0,4,4)%+b"])?;
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn create_repo(&self, remote: Option<&str>) -> Result<(), Error> {
+        self.git(&["init", "--quiet"])?;
+        self.git(&["symbolic-ref", "HEAD", &format!("refs/heads/{}", BRANCH)])?;
+
+        if let Some(remote) = remote {
+            self.git(&["remote", "add", REMOTE, remote])?;
+        }
+
+        Ok(())
+    }
+
+    fn write_key(&self) -> Result<(), Error> {
+        let key_path = self.key_path();
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        // The key is all that protects the pushed snapshots, only its owner may read it.
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        options.open(&key_path)
+            .and_then(|mut file| file.write_all(&self.key))
+            .map_err(|err| Error::write(&path_str(&key_path), err))
+    }
+
+    fn snapshot(&self, rev: &str) -> Result<(PassListModel, Settings), Error> {
+        let snapshot = format!("{}:{}", rev, SNAPSHOT_FILE);
+        let data = self.git(&["show", &snapshot])?.stdout;
+
+        context::read_store_export(&data, &snapshot, "", Some(&self.key))
+    }
+
+    fn write_snapshot(&self, context: &Context) -> Result<(), Error> {
+        let data = context.export(None, "", Some(&self.key), snapshot_params())?;
+        let path = self.dir.join(SNAPSHOT_FILE);
+        fs::write(&path, data).map_err(|err| Error::write(&path_str(&path), err))
+    }
+
+    fn commit_staged(&self, message: &str) -> Result<(), Error> {
+        self.git_as_author(&["commit", "--quiet", "--allow-empty", "-m", message]).map(|_| ())
+    }
+
+    /// Runs a git command creating commits, under a placeholder name when git has none configured.
+    fn git_as_author(&self, args: &[&str]) -> Result<Output, Error> {
+        let mut all_args = vec![];
+        if self.git(&["config", "user.email"]).is_err() {
+            all_args.extend_from_slice(&DEFAULT_AUTHOR);
+        }
+        all_args.extend_from_slice(args);
+
+        self.git(&all_args)
+    }
+
+    fn remote_exists(&self) -> Result<bool, Error> {
+        Ok(self.git(&["rev-parse", "--verify", "--quiet", &remote_branch()]).is_ok())
+    }
+
+    fn has_commits(&self) -> Result<bool, Error> {
+        Ok(self.git(&["rev-parse", "--verify", "--quiet", "HEAD"]).is_ok())
+    }
+
+    fn is_merged(&self, rev: &str) -> Result<bool, Error> {
+        Ok(self.has_commits()? && self.git(&["merge-base", "--is-ancestor", rev, "HEAD"]).is_ok())
+    }
+
+    fn git(&self, args: &[&str]) -> Result<Output, Error> {
+        let output = Command::new(GIT)
+            .arg("-C").arg(&self.dir)
+            .args(args)
+            .output()
+            .map_err(|err| Error::Git(format!("could not run git: {}", err)))?;
+
+        match output.status.success() {
+            true => Ok(output),
+            false => Err(Error::Git(String::from_utf8_lossy(&output.stderr).trim().to_owned())),
+        }
+    }
+}
+
+// The sync key is random, a costly derivation wouldn't make it any harder to guess.
+fn snapshot_params() -> EncryptionParams {
+    EncryptionParams {
+        algorithm: Algorithm::XChaCha20Poly1305,
+        kdf: KdfParams { memory_kib: 8 * 1024, iterations: 1, parallelism: 1 },
+        pbkdf2_iterations: 1,
+    }
+}
+
+fn remote_branch() -> String {
+    format!("{}/{}", REMOTE, BRANCH)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+fn path_str(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pass-history-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn context(max_age: Option<u64>) -> Context {
+        let mut context = Context::unopened("store", Config::default());
+        context.model.insert(String::from("mail"), crate::context::Entry::new(crate::secret::SecretString::from("secret")));
+        context.settings.max_age = max_age;
+        context
+    }
+
+    fn failing_start(_history: &History) -> Result<(), Error> {
+        Err(Error::Git(String::from("failed")))
+    }
+
+    #[test]
+    fn init_failure_removes_new_dir() {
+        let dir = temp_dir("init-new");
+
+        assert!(History::init(&dir, None, None, failing_start).is_err());
+        assert!(!dir.exists());
+
+        let history = History::init(&dir, None, None, |_| Ok(())).unwrap();
+        assert!(history.key_path().exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn init_failure_keeps_existing_dir() {
+        let dir = temp_dir("init-existing");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("notes.txt"), "mine").unwrap();
+
+        let result = History::init(&dir, None, None, |history| {
+            fs::write(history.dir.join(SNAPSHOT_FILE), "snapshot").unwrap();
+            failing_start(history)
+        });
+
+        assert!(result.is_err());
+        assert!(dir.join("notes.txt").exists());
+        assert!(!dir.join(GIT_DIR).exists() && !dir.join(SNAPSHOT_FILE).exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn init_twice() {
+        let dir = temp_dir("init-twice");
+        History::init(&dir, None, None, |_| Ok(())).unwrap();
+
+        assert!(matches!(History::init(&dir, None, None, |_| Ok(())), Err(Error::AlreadyExists(_))));
+
+        let mut config = Config::default();
+        config.git.repo = Some(path_str(&dir));
+        assert!(History::open("store", &config).unwrap().is_some());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn fetch_carries_settings() {
+        let (first_dir, second_dir) = (temp_dir("settings-first"), temp_dir("settings-second"));
+
+        let first = History::init(&first_dir, None, None, |history| history.commit(&context(Some(30)), "Start")).unwrap();
+        let second = History::init(&second_dir, Some(&path_str(&first_dir)), Some(first.key.clone()), |_| Ok(())).unwrap();
+
+        let fetched = second.fetch().unwrap().unwrap();
+        assert!(fetched.base.is_none());
+        assert_eq!(fetched.store["mail"].value.expose(), "secret");
+        assert_eq!(fetched.settings.max_age, Some(30));
+
+        let _ = fs::remove_dir_all(&first_dir);
+        let _ = fs::remove_dir_all(&second_dir);
+    }
+}
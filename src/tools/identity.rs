@@ -0,0 +1,139 @@
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::iter;
+use std::path::Path;
+use std::str::FromStr;
+
+use age::secrecy::ExposeSecret;
+use age::x25519;
+use zeroize::Zeroizing;
+
+use crate::error::{CryptoError, Error, InputError};
+
+const SECRET_KEY_PREFIX: &str = "AGE-SECRET-KEY-";
+const COMMENT_PREFIX: char = '#';
+/// How every binary age file starts.
+const AGE_MAGIC: &[u8] = b"age-encryption.org/";
+const IDENTITY_FILE: &str = "identity file";
+const RECIPIENT: &str = "recipient";
+
+/// The X25519 key pair of the user, kept in an age identity file. Exports sealed to its
+/// public key can be opened with it, by pass or by the age tool.
+pub struct Identity {
+    secret: x25519::Identity,
+}
+
+impl Identity {
+    /// Generates a key pair and writes it to a new file, which only the user can read.
+    pub fn create(path: &Path) -> Result<Identity, Error> {
+        let path_str = path.to_string_lossy().into_owned();
+        let secret = x25519::Identity::generate();
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| Error::write(&path_str, err))?;
+        }
+
+        let contents = Zeroizing::new(format!(
+            "{} public key: {}\n{}\n", COMMENT_PREFIX, secret.to_public(), secret.to_string().expose_secret()));
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        match options.open(path) {
+            Ok(mut file) => file.write_all(contents.as_bytes()).map_err(|err| Error::write(&path_str, err))?,
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => return Err(Error::AlreadyExists(path_str)),
+            Err(err) => return Err(Error::write(&path_str, err)),
+        }
+
+        Ok(Identity { secret })
+    }
+
+    /// Reads the first secret key of an identity file, None when there's no file.
+    pub fn load(path: &Path) -> Result<Option<Identity>, Error> {
+        let path_str = path.to_string_lossy().into_owned();
+
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => Zeroizing::new(contents),
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(Error::read(&path_str, err)),
+        };
+
+        contents.lines()
+            .map(str::trim)
+            .find(|line| line.starts_with(SECRET_KEY_PREFIX))
+            .and_then(|line| x25519::Identity::from_str(line).ok())
+            .map(|secret| Some(Identity { secret }))
+            .ok_or_else(|| Error::parse(&path_str, IDENTITY_FILE))
+    }
+
+    /// The public key to share, like "age1...".
+    pub fn public_key(&self) -> String {
+        self.secret.to_public().to_string()
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let decryptor = age::Decryptor::new_buffered(data).map_err(|_| CryptoError::Format)?;
+
+        let mut reader = decryptor.decrypt(iter::once(&self.secret as &dyn age::Identity))
+            .map_err(|err| match err {
+                age::DecryptError::NoMatchingKeys => Error::Input(InputError::NotRecipient),
+                _ => Error::Crypto(CryptoError::Decrypt),
+            })?;
+
+        let mut decrypted = Zeroizing::new(vec![]);
+        reader.read_to_end(&mut decrypted).map_err(|_| CryptoError::Decrypt)?;
+        Ok(decrypted)
+    }
+}
+
+/// Public keys to seal an export to.
+pub struct Recipients(Vec<x25519::Recipient>);
+
+impl Recipients {
+    /// Each of the specs is a public key or a file with one public key per line,
+    /// like age takes them. Empty lines and "#" comments are skipped.
+    pub fn parse(specs: &[String]) -> Result<Recipients, Error> {
+        let mut recipients = vec![];
+
+        for spec in specs {
+            match x25519::Recipient::from_str(spec) {
+                Ok(recipient) => recipients.push(recipient),
+                Err(_) if Path::new(spec).is_file() => {
+                    let contents = fs::read_to_string(spec).map_err(|err| Error::read(spec, err))?;
+
+                    for line in contents.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with(COMMENT_PREFIX)) {
+                        recipients.push(x25519::Recipient::from_str(line).map_err(|_| Error::parse(spec, RECIPIENT))?);
+                    }
+                },
+                Err(_) => return Err(Error::parse(spec, RECIPIENT)),
+            }
+        }
+
+        Ok(Recipients(recipients))
+    }
+
+    /// Whether the identity can open what's sealed to the recipients.
+    pub fn includes(&self, identity: &Identity) -> bool {
+        let public_key = identity.public_key();
+        self.0.iter().any(|recipient| recipient.to_string() == public_key)
+    }
+
+    /// An age file readable with any of the recipients' identities.
+    pub fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let encryptor = age::Encryptor::with_recipients(self.0.iter().map(|r| r as &dyn age::Recipient))
+            .map_err(|_| CryptoError::Encrypt)?;
+
+        let mut encrypted = vec![];
+        let mut writer = encryptor.wrap_output(&mut encrypted).map_err(|_| CryptoError::Encrypt)?;
+        writer.write_all(data).and_then(|_| writer.finish()).map_err(|_| CryptoError::Encrypt)?;
+
+        Ok(encrypted)
+    }
+}
+
+/// Whether the data is an age file rather than a passphrase export.
+pub fn is_age(data: &[u8]) -> bool {
+    data.starts_with(AGE_MAGIC)
+}
@@ -0,0 +1,456 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use chacha20::ChaCha20;
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::{Digest, Sha256, Sha512};
+use zeroize::Zeroizing;
+
+use crate::context::{Entry, PassListModel};
+use crate::error::CryptoError;
+use super::encryption::{self, KdfParams};
+
+const SIGNATURE_1: u32 = 0x9AA2_D903;
+const SIGNATURE_2: u32 = 0xB54B_FB67;
+const FILE_VERSION: u32 = 0x0004_0000;
+
+const CIPHER_CHACHA20: [u8; 16] = [
+    0xD6, 0x03, 0x8A, 0x2B, 0x8B, 0x6F, 0x4C, 0xB5, 0xA5, 0x24, 0x33, 0x9A, 0x31, 0xDB, 0xB5, 0x9A,
+];
+const KDF_ARGON2ID: [u8; 16] = [
+    0x9E, 0x29, 0x8B, 0x19, 0x56, 0xDB, 0x47, 0x73, 0xB2, 0x3D, 0xFC, 0x3E, 0xC6, 0xF0, 0xA1, 0xE6,
+];
+
+const HEADER_END: u8 = 0;
+const HEADER_CIPHER_ID: u8 = 2;
+const HEADER_COMPRESSION: u8 = 3;
+const HEADER_MASTER_SEED: u8 = 4;
+const HEADER_ENCRYPTION_IV: u8 = 7;
+const HEADER_KDF_PARAMETERS: u8 = 11;
+
+const INNER_END: u8 = 0;
+const INNER_STREAM_ID: u8 = 1;
+const INNER_STREAM_KEY: u8 = 2;
+const INNER_STREAM_CHACHA20: u32 = 3;
+
+const VARIANT_VERSION: u16 = 0x0100;
+const VARIANT_UINT32: u8 = 0x04;
+const VARIANT_UINT64: u8 = 0x05;
+const VARIANT_BYTES: u8 = 0x42;
+
+const ARGON2_VERSION: u32 = 0x13;
+
+const BLOCK_SIZE: usize = 1024 * 1024;
+const ROOT_GROUP_NAME: &str = "pass";
+const FOLDER_SEPARATOR: char = '/';
+// Seconds from 0001-01-01, where KDBX 4 times count from, to the unix epoch.
+const UNIX_EPOCH_SECONDS: i64 = 62_135_596_800;
+
+/// Builds a KDBX 4 database from the model, protected by the given passphrase.
+/// Keys are split on '/', every leading component becomes a group. See renamed for
+/// the keys that can't keep the last component as their title.
+pub fn export(model: &PassListModel, pass: &str, key_file: Option<&[u8]>, kdf: KdfParams) -> Result<Vec<u8>, CryptoError> {
+    let master_seed = random_bytes(32);
+    let encryption_iv = random_bytes(12);
+    let kdf_salt = random_bytes(32);
+    let stream_key = random_bytes(64);
+
+    let transformed_key = transform_key(pass, key_file, kdf, &kdf_salt)?;
+
+    let mut seed_and_key = Zeroizing::new(master_seed.clone());
+    seed_and_key.extend_from_slice(&transformed_key);
+    let encryption_key = Sha256::digest(&*seed_and_key);
+    seed_and_key.push(1);
+    let hmac_key = Sha512::digest(&*seed_and_key);
+
+    let header = outer_header(&master_seed, &encryption_iv, kdf, &kdf_salt);
+
+    let mut result = header.clone();
+    result.extend_from_slice(&Sha256::digest(&header));
+    result.extend_from_slice(&hmac_sha256(&block_key(&hmac_key, u64::MAX), &[&header])?);
+
+    let mut payload = inner_header(&stream_key);
+    payload.extend_from_slice(build_xml(model, &stream_key).as_bytes());
+
+    let mut cipher = ChaCha20::new(encryption_key.as_slice().into(), encryption_iv.as_slice().into());
+    cipher.apply_keystream(&mut payload);
+
+    let blocks = payload.chunks(BLOCK_SIZE).collect::<Vec<&[u8]>>();
+    for (index, block) in blocks.iter().enumerate() {
+        write_block(&mut result, &hmac_key, index as u64, block)?;
+    }
+    write_block(&mut result, &hmac_key, blocks.len() as u64, &[])?;
+
+    Ok(result)
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+fn transform_key(pass: &str, key_file: Option<&[u8]>, kdf: KdfParams, salt: &[u8]) -> Result<Zeroizing<Vec<u8>>, CryptoError> {
+    let mut components = Zeroizing::new(Sha256::digest(pass.as_bytes()).to_vec());
+    if let Some(key) = key_file {
+        components.extend_from_slice(&encryption::key_file_hash(key));
+    }
+
+    kdf.derive_key(&Sha256::digest(&*components), salt)
+}
+
+fn outer_header(master_seed: &[u8], encryption_iv: &[u8], kdf: KdfParams, kdf_salt: &[u8]) -> Vec<u8> {
+    let mut header = vec![];
+    header.extend_from_slice(&SIGNATURE_1.to_le_bytes());
+    header.extend_from_slice(&SIGNATURE_2.to_le_bytes());
+    header.extend_from_slice(&FILE_VERSION.to_le_bytes());
+
+    write_field(&mut header, HEADER_CIPHER_ID, &CIPHER_CHACHA20);
+    write_field(&mut header, HEADER_COMPRESSION, &0u32.to_le_bytes());
+    write_field(&mut header, HEADER_MASTER_SEED, master_seed);
+    write_field(&mut header, HEADER_ENCRYPTION_IV, encryption_iv);
+    write_field(&mut header, HEADER_KDF_PARAMETERS, &kdf_parameters(kdf, kdf_salt));
+    write_field(&mut header, HEADER_END, b"\r\n\r\n");
+
+    header
+}
+
+fn inner_header(stream_key: &[u8]) -> Vec<u8> {
+    let mut header = vec![];
+    write_field(&mut header, INNER_STREAM_ID, &INNER_STREAM_CHACHA20.to_le_bytes());
+    write_field(&mut header, INNER_STREAM_KEY, stream_key);
+    write_field(&mut header, INNER_END, &[]);
+    header
+}
+
+fn write_field(out: &mut Vec<u8>, id: u8, data: &[u8]) {
+    out.push(id);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+}
+
+fn kdf_parameters(kdf: KdfParams, salt: &[u8]) -> Vec<u8> {
+    let mut dict = VARIANT_VERSION.to_le_bytes().to_vec();
+    write_variant(&mut dict, VARIANT_BYTES, "$UUID", &KDF_ARGON2ID);
+    write_variant(&mut dict, VARIANT_BYTES, "S", salt);
+    write_variant(&mut dict, VARIANT_UINT32, "P", &kdf.parallelism.to_le_bytes());
+    write_variant(&mut dict, VARIANT_UINT64, "M", &(kdf.memory_kib as u64 * 1024).to_le_bytes());
+    write_variant(&mut dict, VARIANT_UINT64, "I", &(kdf.iterations as u64).to_le_bytes());
+    write_variant(&mut dict, VARIANT_UINT32, "V", &ARGON2_VERSION.to_le_bytes());
+    dict.push(0);
+    dict
+}
+
+fn write_variant(out: &mut Vec<u8>, kind: u8, name: &str, value: &[u8]) {
+    out.push(kind);
+    out.extend_from_slice(&(name.len() as i32).to_le_bytes());
+    out.extend_from_slice(name.as_bytes());
+    out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> Result<Vec<u8>, CryptoError> {
+    let mut mac = match Hmac::<Sha256>::new_from_slice(key) {
+        Ok(m) => m,
+        Err(_) => return Err(CryptoError::Encrypt),
+    };
+
+    parts.iter().for_each(|part| mac.update(part));
+
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+fn block_key(hmac_key: &[u8], index: u64) -> Vec<u8> {
+    let mut key_source = index.to_le_bytes().to_vec();
+    key_source.extend_from_slice(hmac_key);
+    Sha512::digest(&key_source).to_vec()
+}
+
+fn write_block(out: &mut Vec<u8>, hmac_key: &[u8], index: u64, block: &[u8]) -> Result<(), CryptoError> {
+    let size = (block.len() as i32).to_le_bytes();
+    let hmac = hmac_sha256(&block_key(hmac_key, index), &[&index.to_le_bytes(), &size, block])?;
+
+    out.extend_from_slice(&hmac);
+    out.extend_from_slice(&size);
+    out.extend_from_slice(block);
+    Ok(())
+}
+
+#[derive(Default)]
+struct Group<'a> {
+    groups: BTreeMap<&'a str, Group<'a>>,
+    entries: Vec<(&'a str, &'a Entry)>,
+}
+
+/// Keys titled with the whole key in the database, since another key ends up with the same
+/// group and title, like "a//b" and "a/b".
+pub fn renamed(model: &PassListModel) -> Vec<&str> {
+    build_tree(model).1
+}
+
+fn build_tree(model: &PassListModel) -> (Group<'_>, Vec<&str>) {
+    let mut root = Group::default();
+    let mut renamed = vec![];
+
+    // Keys without empty components keep the short title, the order is stable otherwise.
+    let mut entries = model.iter().collect::<Vec<(&String, &Entry)>>();
+    entries.sort_by_key(|(key, _)| (key.split(FOLDER_SEPARATOR).any(str::is_empty), *key));
+
+    for (key, entry) in entries {
+        let mut parts = key.split(FOLDER_SEPARATOR).filter(|p| !p.is_empty()).collect::<Vec<&str>>();
+        let title = parts.pop().unwrap_or(key);
+
+        let group = parts.into_iter().fold(&mut root, |group, name| group.groups.entry(name).or_default());
+
+        let title = match group.entries.iter().any(|(taken, _)| *taken == title) {
+            true => {
+                renamed.push(key.as_str());
+                key.as_str()
+            },
+            false => title,
+        };
+        group.entries.push((title, entry));
+    }
+
+    (root, renamed)
+}
+
+struct ProtectedStream {
+    cipher: ChaCha20,
+}
+
+impl ProtectedStream {
+    fn new(stream_key: &[u8]) -> ProtectedStream {
+        let hash = Sha512::digest(stream_key);
+        ProtectedStream { cipher: ChaCha20::new(hash[..32].into(), hash[32..44].into()) }
+    }
+
+    fn protect(&mut self, value: &str) -> String {
+        let mut bytes = Zeroizing::new(value.as_bytes().to_vec());
+        self.cipher.apply_keystream(&mut bytes);
+        base64::encode(&*bytes)
+    }
+}
+
+fn build_xml(model: &PassListModel, stream_key: &[u8]) -> String {
+    let mut stream = ProtectedStream::new(stream_key);
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\" standalone=\"yes\"?>");
+
+    xml.push_str("<KeePassFile><Meta><Generator>pass</Generator>");
+    xml.push_str(&format!("<DatabaseName>{}</DatabaseName></Meta><Root>", ROOT_GROUP_NAME));
+    write_group(&mut xml, ROOT_GROUP_NAME, &build_tree(model).0, &mut stream);
+    xml.push_str("</Root></KeePassFile>");
+
+    xml
+}
+
+fn write_group(xml: &mut String, name: &str, group: &Group, stream: &mut ProtectedStream) {
+    xml.push_str(&format!("<Group><UUID>{}</UUID><Name>{}</Name>", new_uuid(), escape(name)));
+
+    for (title, entry) in group.entries.iter() {
+        xml.push_str(&format!("<Entry><UUID>{}</UUID>", new_uuid()));
+        if entry.modified != 0 {
+            xml.push_str(&format!("<Times><LastModificationTime>{}</LastModificationTime></Times>", time(entry.modified)));
+        }
+        if !entry.tags.is_empty() {
+            xml.push_str(&format!("<Tags>{}</Tags>", escape(&entry.tags.join(";"))));
+        }
+        xml.push_str(&format!("<String><Key>Title</Key><Value>{}</Value></String>", escape(title)));
+        xml.push_str(&format!("<String><Key>Password</Key><Value Protected=\"True\">{}</Value></String>", stream.protect(entry.value.expose())));
+        xml.push_str("</Entry>");
+    }
+
+    for (name, subgroup) in group.groups.iter() {
+        write_group(xml, name, subgroup, stream);
+    }
+
+    xml.push_str("</Group>");
+}
+
+/// KDBX 4 times are base64 of the little endian seconds since 0001-01-01 UTC.
+fn time(unix_seconds: u64) -> String {
+    let seconds = i64::try_from(unix_seconds).unwrap_or(i64::MAX).saturating_add(UNIX_EPOCH_SECONDS);
+    base64::encode(seconds.to_le_bytes())
+}
+
+fn new_uuid() -> String {
+    base64::encode(random_bytes(16))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secret::SecretString;
+
+    const SMALL_KDF: KdfParams = KdfParams { memory_kib: 8 * 1024, iterations: 1, parallelism: 1 };
+
+    fn model(keys: &[(&str, &str)]) -> PassListModel {
+        keys.iter()
+            .map(|&(key, value)| {
+                let mut entry = Entry::new(SecretString::from(value));
+                entry.modified = 1_000_000_000;
+                (String::from(key), entry)
+            })
+            .collect()
+    }
+
+    fn read_field(data: &[u8], pos: &mut usize) -> (u8, Vec<u8>) {
+        let id = data[*pos];
+        let len = u32::from_le_bytes(<[u8; 4]>::try_from(&data[*pos + 1..*pos + 5]).unwrap()) as usize;
+        *pos += 5 + len;
+        (id, data[*pos - len..*pos].to_vec())
+    }
+
+    fn kdf_salt(dict: &[u8]) -> Vec<u8> {
+        let mut pos = 2;
+        loop {
+            let name_len = i32::from_le_bytes(<[u8; 4]>::try_from(&dict[pos + 1..pos + 5]).unwrap()) as usize;
+            let name = &dict[pos + 5..pos + 5 + name_len];
+            pos += 5 + name_len;
+            let value_len = i32::from_le_bytes(<[u8; 4]>::try_from(&dict[pos..pos + 4]).unwrap()) as usize;
+            if name == b"S" {
+                return dict[pos + 4..pos + 4 + value_len].to_vec();
+            }
+            pos += 4 + value_len;
+        }
+    }
+
+    // Reads the database back the way KeePass does, checking every hash and HMAC on the way.
+    fn open(data: &[u8], pass: &str, key_file: Option<&[u8]>) -> Result<String, CryptoError> {
+        assert_eq!(&data[..4], &SIGNATURE_1.to_le_bytes());
+        assert_eq!(&data[4..8], &SIGNATURE_2.to_le_bytes());
+        assert_eq!(&data[8..12], &FILE_VERSION.to_le_bytes());
+
+        let mut fields = BTreeMap::new();
+        let mut pos = 12;
+        loop {
+            let (id, value) = read_field(data, &mut pos);
+            if id == HEADER_END { break }
+            fields.insert(id, value);
+        }
+        assert_eq!(fields[&HEADER_CIPHER_ID], CIPHER_CHACHA20);
+
+        let header = &data[..pos];
+        assert_eq!(&data[pos..pos + 32], Sha256::digest(header).as_slice());
+
+        let transformed_key = transform_key(pass, key_file, SMALL_KDF, &kdf_salt(&fields[&HEADER_KDF_PARAMETERS]))?;
+        let mut seed_and_key = fields[&HEADER_MASTER_SEED].clone();
+        seed_and_key.extend_from_slice(&transformed_key);
+        let encryption_key = Sha256::digest(&seed_and_key);
+        seed_and_key.push(1);
+        let hmac_key = Sha512::digest(&seed_and_key);
+
+        if data[pos + 32..pos + 64] != hmac_sha256(&block_key(&hmac_key, u64::MAX), &[header])?[..] {
+            return Err(CryptoError::Decrypt);
+        }
+        pos += 64;
+
+        let mut payload = vec![];
+        for index in 0.. {
+            let size = i32::from_le_bytes(<[u8; 4]>::try_from(&data[pos + 32..pos + 36]).unwrap()) as usize;
+            let block = &data[pos + 36..pos + 36 + size];
+            let hmac = hmac_sha256(&block_key(&hmac_key, index), &[&index.to_le_bytes(), &data[pos + 32..pos + 36], block])?;
+            assert_eq!(&data[pos..pos + 32], &hmac[..]);
+            pos += 36 + size;
+            if size == 0 { break }
+            payload.extend_from_slice(block);
+        }
+        assert_eq!(pos, data.len());
+
+        let mut cipher = ChaCha20::new(encryption_key.as_slice().into(), fields[&HEADER_ENCRYPTION_IV].as_slice().into());
+        cipher.apply_keystream(&mut payload);
+
+        let mut pos = 0;
+        let mut stream_key = vec![];
+        loop {
+            let (id, value) = read_field(&payload, &mut pos);
+            match id {
+                INNER_END => break,
+                INNER_STREAM_KEY => stream_key = value,
+                _ => {},
+            }
+        }
+
+        Ok(unprotect(std::str::from_utf8(&payload[pos..]).unwrap(), &stream_key))
+    }
+
+    // Replaces the protected values by the plain ones, they're encrypted in document order.
+    fn unprotect(xml: &str, stream_key: &[u8]) -> String {
+        const PROTECTED: &str = "<Value Protected=\"True\">";
+        let mut stream = ProtectedStream::new(stream_key);
+        let mut parts = xml.split(PROTECTED);
+        let mut result = String::from(parts.next().unwrap());
+
+        for part in parts {
+            let end = part.find('<').unwrap();
+            let mut value = base64::decode(&part[..end]).unwrap();
+            stream.cipher.apply_keystream(&mut value);
+            result.push_str("<Value>");
+            result.push_str(std::str::from_utf8(&value).unwrap());
+            result.push_str(&part[end..]);
+        }
+
+        result
+    }
+
+    fn entry_xml(title: &str, password: &str) -> String {
+        format!("<String><Key>Title</Key><Value>{}</Value></String><String><Key>Password</Key><Value>{}</Value></String>", title, password)
+    }
+
+    #[test]
+    fn round_trip() {
+        let model = model(&[("mail", "secret"), ("web/shop", "<other>"), ("web/forum", "third")]);
+
+        for &key_file in &[None, Some(&b"key file"[..])] {
+            let data = export(&model, "pass", key_file, SMALL_KDF).unwrap();
+            let xml = open(&data, "pass", key_file).unwrap();
+
+            assert!(xml.contains("<Name>pass</Name>") && xml.contains("<Name>web</Name>"));
+            assert!(xml.contains(&entry_xml("mail", "secret")));
+            assert!(xml.contains(&entry_xml("shop", "<other>")));
+            assert!(xml.contains(&entry_xml("forum", "third")));
+        }
+    }
+
+    #[test]
+    fn wrong_secret() {
+        let data = export(&model(&[("mail", "secret")]), "pass", Some(b"key file"), SMALL_KDF).unwrap();
+
+        assert!(matches!(open(&data, "other", Some(b"key file")), Err(CryptoError::Decrypt)));
+        assert!(matches!(open(&data, "pass", None), Err(CryptoError::Decrypt)));
+    }
+
+    #[test]
+    fn colliding_titles() {
+        let model = model(&[("a/b", "first"), ("a//b", "second"), ("/a/b/", "third"), ("c", "fourth")]);
+        let mut renamed = renamed(&model);
+        renamed.sort_unstable();
+        assert_eq!(renamed, vec!["/a/b/", "a//b"]);
+
+        let xml = open(&export(&model, "pass", None, SMALL_KDF).unwrap(), "pass", None).unwrap();
+        assert!(xml.contains(&entry_xml("b", "first")));
+        assert!(xml.contains(&entry_xml("a//b", "second")));
+        assert!(xml.contains(&entry_xml("/a/b/", "third")));
+        assert!(xml.contains(&entry_xml("c", "fourth")));
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(time(0), base64::encode(UNIX_EPOCH_SECONDS.to_le_bytes()));
+        assert_eq!(time(1_000_000_000), base64::encode(63_135_596_800i64.to_le_bytes()));
+        assert_eq!(time(u64::MAX), base64::encode(i64::MAX.to_le_bytes()));
+
+        let xml = open(&export(&model(&[("mail", "secret")]), "pass", None, SMALL_KDF).unwrap(), "pass", None).unwrap();
+        assert!(xml.contains(&format!("<Times><LastModificationTime>{}</LastModificationTime></Times>", time(1_000_000_000))));
+    }
+}
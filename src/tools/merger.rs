@@ -0,0 +1,392 @@
+use std::collections::{BTreeSet, HashMap};
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+
+use crate::context::{self, Entry, PassListModel};
+use crate::error::Error;
+
+const SALT_LEN: usize = 32;
+
+/// What to do with all the keys having different values on both sides.
+pub enum Resolution {
+    KeepOld,
+    TakeNew,
+    /// The value changed last, by the modification times. A removal is older than any change.
+    Newest,
+    /// Keep the old value and add the new one under the key with a suffix.
+    KeepBoth,
+    /// Ask for every key.
+    PerKey,
+    Cancel,
+}
+
+pub enum Choice {
+    Old,
+    New,
+}
+
+/// Decides the conflicts of a merge, the CLI asks on the terminal.
+pub trait ConflictResolver {
+    fn resolve(&mut self, collisions: &[String]) -> Result<Resolution, Error>;
+    fn choose(&mut self, key: &str) -> Result<Choice, Error>;
+}
+
+/// Resolves all conflicts the same way without asking, for unattended imports.
+#[derive(Clone, Copy)]
+pub enum Strategy {
+    Ours,
+    Theirs,
+    Newest,
+    /// Fails on the first conflict, before anything is changed.
+    Fail,
+    KeepBoth,
+}
+
+impl Strategy {
+    pub fn from_name(name: &str) -> Option<Strategy> {
+        match name {
+            "ours" => Some(Strategy::Ours),
+            "theirs" => Some(Strategy::Theirs),
+            "newest" => Some(Strategy::Newest),
+            "fail" => Some(Strategy::Fail),
+            "keep-both" => Some(Strategy::KeepBoth),
+            _ => None,
+        }
+    }
+}
+
+impl ConflictResolver for Strategy {
+    fn resolve(&mut self, collisions: &[String]) -> Result<Resolution, Error> {
+        match self {
+            Strategy::Ours => Ok(Resolution::KeepOld),
+            Strategy::Theirs => Ok(Resolution::TakeNew),
+            Strategy::Newest => Ok(Resolution::Newest),
+            Strategy::Fail => Err(Error::Conflict(collisions.to_vec())),
+            Strategy::KeepBoth => Ok(Resolution::KeepBoth),
+        }
+    }
+
+    fn choose(&mut self, _key: &str) -> Result<Choice, Error> {
+        match self {
+            Strategy::Theirs => Ok(Choice::New),
+            _ => Ok(Choice::Old),
+        }
+    }
+}
+
+/// The keys a merge touched, in alphabetical order.
+#[derive(Default)]
+pub struct Summary {
+    /// Including the suffixed keys of kept new values.
+    pub added: Vec<String>,
+    pub overwritten: Vec<String>,
+    pub removed: Vec<String>,
+    /// Conflicting keys that kept their old value.
+    pub skipped: Vec<String>,
+}
+
+/// Suffix of the key the new value goes under when both are kept, numbered when taken too.
+pub const KEEP_BOTH_SUFFIX: &str = ".theirs";
+
+/// Adds the new entries to the old ones. When the same key has different values,
+/// the resolver decides, on cancel nothing is changed and None is returned.
+pub fn merge(new: PassListModel, old: &mut PassListModel, resolver: &mut dyn ConflictResolver) -> Result<Option<Summary>, Error> {
+    let collisions = context::find_collisions(&new, old).into_iter()
+        .cloned()
+        .collect::<Vec<String>>();
+
+    let added = new.keys()
+        .filter(|key| !old.contains_key(*key))
+        .cloned()
+        .collect::<Vec<String>>();
+
+    apply(new, old, added, collisions, resolver)
+}
+
+/// A store as both sides last agreed on it, to tell who changed an entry since.
+/// Only salted hashes of the entries are kept, so it can be saved without the passwords.
+pub struct Base {
+    salt: Vec<u8>,
+    entries: HashMap<String, Vec<u8>>,
+}
+
+impl Base {
+    pub fn of(model: &PassListModel) -> Base {
+        let mut salt = vec![0; SALT_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+
+        let mut base = Base { salt, entries: HashMap::new() };
+        base.entries = model.iter().map(|(key, entry)| (key.clone(), base.fingerprint(entry))).collect();
+        base
+    }
+
+    pub fn from_parts(salt: Vec<u8>, entries: HashMap<String, Vec<u8>>) -> Base {
+        Base { salt, entries }
+    }
+
+    pub fn salt(&self) -> &[u8] {
+        &self.salt
+    }
+
+    pub fn entries(&self) -> &HashMap<String, Vec<u8>> {
+        &self.entries
+    }
+
+    /// Whether the entry, or its absence, is what the key had in the base.
+    pub fn matches(&self, key: &str, entry: Option<&Entry>) -> bool {
+        match (self.entries.get(key), entry) {
+            (None, None) => true,
+            (Some(fingerprint), Some(entry)) => *fingerprint == self.fingerprint(entry),
+            _ => false,
+        }
+    }
+
+    // The modification time is left out, setting the same password on both sides is no conflict.
+    fn fingerprint(&self, entry: &Entry) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.salt).expect("HMAC takes keys of any size");
+        mac.update(entry.value.expose().as_bytes());
+        mac.update(&[0]);
+        mac.update(entry.max_age.map(|d| d.to_string()).unwrap_or_default().as_bytes());
+        if !entry.tags.is_empty() {
+            mac.update(&[0]);
+            mac.update(entry.tags.join(",").as_bytes());
+        }
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+/// Merges the new side into the old one, knowing what both looked like when they last agreed.
+/// Entries changed, added or removed on one side only are taken from that side, so removals
+/// are carried over too. Only keys changed differently on both sides are left to the resolver,
+/// where taking the new side of a key removed there removes it here. On cancel nothing is changed
+/// and None is returned.
+pub fn merge_with_base(new: PassListModel, old: &mut PassListModel, base: &Base, resolver: &mut dyn ConflictResolver) -> Result<Option<Summary>, Error> {
+    let keys = new.keys().chain(old.keys()).cloned().collect::<BTreeSet<String>>();
+
+    let mut taken = vec![];
+    let mut collisions = vec![];
+
+    for key in keys {
+        let (theirs, ours) = (new.get(&key), old.get(&key));
+
+        if same(theirs, ours) || base.matches(&key, theirs) {
+            continue;
+        }
+
+        match base.matches(&key, ours) {
+            true => taken.push(key),
+            false => collisions.push(key),
+        }
+    }
+
+    apply(new, old, taken, collisions, resolver)
+}
+
+// All the choices are made before anything changes, so a failing resolver leaves the old entries as they were.
+fn apply(mut new: PassListModel, old: &mut PassListModel, mut taken: Vec<String>, mut collisions: Vec<String>,
+         resolver: &mut dyn ConflictResolver) -> Result<Option<Summary>, Error> {
+    let mut summary = Summary::default();
+    let mut kept_both = vec![];
+
+    collisions.sort();
+
+    if !collisions.is_empty() {
+        match resolver.resolve(&collisions)? {
+            Resolution::KeepOld => summary.skipped = collisions,
+            Resolution::TakeNew => taken.append(&mut collisions),
+            Resolution::Newest => for key in collisions {
+                match modified(new.get(&key)) > modified(old.get(&key)) {
+                    true => taken.push(key),
+                    false => summary.skipped.push(key),
+                }
+            },
+            Resolution::KeepBoth => kept_both = collisions,
+            Resolution::PerKey => for key in collisions {
+                match resolver.choose(&key)? {
+                    Choice::New => taken.push(key),
+                    Choice::Old => summary.skipped.push(key),
+                }
+            },
+            Resolution::Cancel => return Ok(None),
+        }
+    }
+
+    for key in taken {
+        match new.remove(&key) {
+            Some(entry) => {
+                match old.contains_key(&key) {
+                    true => summary.overwritten.push(key.clone()),
+                    false => summary.added.push(key.clone()),
+                }
+                old.insert(key, entry);
+            },
+            None => {
+                old.remove(&key);
+                summary.removed.push(key);
+            },
+        }
+    }
+
+    for key in kept_both {
+        match new.remove(&key) {
+            Some(entry) => {
+                let free_key = match old.contains_key(&key) {
+                    true => suffixed_key(old, &key),
+                    false => key,
+                };
+                summary.added.push(free_key.clone());
+                old.insert(free_key, entry);
+            },
+            // Removed on the new side, the old value is all there is to keep.
+            None => summary.skipped.push(key),
+        }
+    }
+
+    summary.added.sort();
+    summary.overwritten.sort();
+    summary.removed.sort();
+    summary.skipped.sort();
+
+    Ok(Some(summary))
+}
+
+fn suffixed_key(model: &PassListModel, key: &str) -> String {
+    let mut candidate = format!("{}{}", key, KEEP_BOTH_SUFFIX);
+    let mut n = 2;
+
+    while model.contains_key(&candidate) {
+        candidate = format!("{}{}{}", key, KEEP_BOTH_SUFFIX, n);
+        n += 1;
+    }
+
+    candidate
+}
+
+fn modified(entry: Option<&Entry>) -> u64 {
+    entry.map(|e| e.modified).unwrap_or(0)
+}
+
+fn same(a: Option<&Entry>, b: Option<&Entry>) -> bool {
+    match (a, b) {
+        (None, None) => true,
+        (Some(a), Some(b)) => a.value == b.value && a.max_age == b.max_age && a.tags == b.tags,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secret::SecretString;
+
+    fn model(entries: &[(&str, &str, u64)]) -> PassListModel {
+        entries.iter()
+            .map(|&(key, value, modified)| {
+                let mut entry = Entry::new(SecretString::from(value));
+                entry.modified = modified;
+                (String::from(key), entry)
+            })
+            .collect()
+    }
+
+    fn values(model: &PassListModel) -> Vec<(&str, &str)> {
+        let mut values = model.iter().map(|(key, entry)| (key.as_str(), entry.value.expose())).collect::<Vec<(&str, &str)>>();
+        values.sort();
+        values
+    }
+
+    // "both" differs on the two sides and is newer on ours, "new" is newer on theirs.
+    fn merged(mut strategy: Strategy) -> Result<(PassListModel, Summary), Error> {
+        let mut old = model(&[("both", "ours", 200), ("new", "ours", 100), ("same", "same", 100), ("only-ours", "ours", 100)]);
+        let new = model(&[("both", "theirs", 100), ("new", "theirs", 200), ("same", "same", 300), ("only-theirs", "theirs", 100)]);
+
+        let summary = merge(new, &mut old, &mut strategy)?.unwrap();
+        Ok((old, summary))
+    }
+
+    #[test]
+    fn ours() {
+        let (model, summary) = merged(Strategy::Ours).unwrap();
+
+        assert_eq!(values(&model), vec![("both", "ours"), ("new", "ours"), ("only-ours", "ours"), ("only-theirs", "theirs"), ("same", "same")]);
+        assert_eq!(summary.added, vec!["only-theirs"]);
+        assert_eq!(summary.skipped, vec!["both", "new"]);
+        assert!(summary.overwritten.is_empty() && summary.removed.is_empty());
+    }
+
+    #[test]
+    fn theirs() {
+        let (model, summary) = merged(Strategy::Theirs).unwrap();
+
+        assert_eq!(values(&model), vec![("both", "theirs"), ("new", "theirs"), ("only-ours", "ours"), ("only-theirs", "theirs"), ("same", "same")]);
+        assert_eq!(summary.added, vec!["only-theirs"]);
+        assert_eq!(summary.overwritten, vec!["both", "new"]);
+        assert!(summary.skipped.is_empty());
+    }
+
+    #[test]
+    fn newest() {
+        let (model, summary) = merged(Strategy::Newest).unwrap();
+
+        assert_eq!(values(&model), vec![("both", "ours"), ("new", "theirs"), ("only-ours", "ours"), ("only-theirs", "theirs"), ("same", "same")]);
+        assert_eq!(summary.overwritten, vec!["new"]);
+        assert_eq!(summary.skipped, vec!["both"]);
+    }
+
+    #[test]
+    fn keep_both() {
+        let (model, summary) = merged(Strategy::KeepBoth).unwrap();
+
+        assert_eq!(values(&model), vec![
+            ("both", "ours"), ("both.theirs", "theirs"), ("new", "ours"), ("new.theirs", "theirs"),
+            ("only-ours", "ours"), ("only-theirs", "theirs"), ("same", "same"),
+        ]);
+        assert_eq!(summary.added, vec!["both.theirs", "new.theirs", "only-theirs"]);
+        assert!(summary.overwritten.is_empty() && summary.skipped.is_empty());
+    }
+
+    #[test]
+    fn keep_both_numbered() {
+        let mut old = model(&[("mail", "ours", 100), ("mail.theirs", "earlier", 100)]);
+        let new = model(&[("mail", "theirs", 100)]);
+
+        let summary = merge(new, &mut old, &mut Strategy::KeepBoth).unwrap().unwrap();
+
+        assert_eq!(summary.added, vec!["mail.theirs2"]);
+        assert_eq!(old["mail.theirs2"].value.expose(), "theirs");
+    }
+
+    #[test]
+    fn fail() {
+        let mut old = model(&[("both", "ours", 100)]);
+        let new = model(&[("both", "theirs", 100), ("only-theirs", "theirs", 100)]);
+
+        match merge(new, &mut old, &mut Strategy::Fail) {
+            Err(Error::Conflict(keys)) => assert_eq!(keys, vec!["both"]),
+            _ => panic!("expected a conflict"),
+        }
+        assert_eq!(values(&old), vec![("both", "ours")]);
+    }
+
+    #[test]
+    fn with_base() {
+        let base_model = model(&[("changed-ours", "base", 100), ("changed-theirs", "base", 100), ("changed-both", "base", 100),
+                                 ("removed-theirs", "base", 100), ("removed-ours", "base", 100)]);
+        let base = Base::of(&base_model);
+
+        let mut old = model(&[("changed-ours", "ours", 100), ("changed-theirs", "base", 100), ("changed-both", "ours", 100),
+                              ("removed-theirs", "base", 100)]);
+        let new = model(&[("changed-ours", "base", 100), ("changed-theirs", "theirs", 100), ("changed-both", "theirs", 100),
+                          ("removed-ours", "base", 100), ("added-theirs", "theirs", 100)]);
+
+        let summary = merge_with_base(new, &mut old, &base, &mut Strategy::Ours).unwrap().unwrap();
+
+        assert_eq!(values(&old), vec![("added-theirs", "theirs"), ("changed-both", "ours"), ("changed-ours", "ours"), ("changed-theirs", "theirs")]);
+        assert_eq!(summary.added, vec!["added-theirs"]);
+        assert_eq!(summary.overwritten, vec!["changed-theirs"]);
+        assert_eq!(summary.removed, vec!["removed-theirs"]);
+        assert_eq!(summary.skipped, vec!["changed-both"]);
+    }
+}
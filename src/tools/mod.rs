@@ -0,0 +1,13 @@
+pub mod backup;
+pub mod breach;
+pub mod calibration;
+pub mod changes;
+pub mod encryption;
+pub mod generator;
+pub mod history;
+pub mod identity;
+pub mod kdbx;
+pub mod merger;
+pub mod signature;
+pub mod strength;
+pub mod sync;
@@ -0,0 +1,137 @@
+use std::convert::{TryFrom, TryInto};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey, PUBLIC_KEY_LENGTH, SECRET_KEY_LENGTH, SIGNATURE_LENGTH};
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+use crate::error::{Error, SignatureError};
+
+/// Starts a signed export, followed by the public key, the signature and the export itself.
+const MAGIC: &[u8] = b"PASS-SIGNED-V1\0";
+/// Signed along with the export, so the signature means nothing to other programs.
+const DOMAIN: &[u8] = b"pass export signature v1\0";
+const PUBLIC_KEY_PREFIX: &str = "ed25519:";
+const COMMENT_PREFIX: char = '#';
+const SIGNING_KEY_FILE: &str = "signing key file";
+
+/// The Ed25519 key the user signs exports with, kept base64 encoded in a file of its own.
+pub struct SigningIdentity {
+    key: SigningKey,
+}
+
+/// An export with a valid signature.
+pub struct Signed<'a> {
+    /// Public key of the signer, like "ed25519:...".
+    pub signer: String,
+    pub export: &'a [u8],
+}
+
+impl SigningIdentity {
+    /// Generates a key and writes it to a new file, which only the user can read.
+    pub fn create(path: &Path) -> Result<SigningIdentity, Error> {
+        let path_str = path.to_string_lossy().into_owned();
+
+        let mut secret = Zeroizing::new([0; SECRET_KEY_LENGTH]);
+        rand::thread_rng().fill_bytes(&mut *secret);
+        let key = SigningKey::from_bytes(&secret);
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| Error::write(&path_str, err))?;
+        }
+
+        let contents = Zeroizing::new(format!(
+            "{} public key: {}\n{}\n", COMMENT_PREFIX, public_key(&key.verifying_key()), base64::encode(&secret[..])));
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        match options.open(path) {
+            Ok(mut file) => file.write_all(contents.as_bytes()).map_err(|err| Error::write(&path_str, err))?,
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => return Err(Error::AlreadyExists(path_str)),
+            Err(err) => return Err(Error::write(&path_str, err)),
+        }
+
+        Ok(SigningIdentity { key })
+    }
+
+    /// Reads the key, None when there's no file.
+    pub fn load(path: &Path) -> Result<Option<SigningIdentity>, Error> {
+        let path_str = path.to_string_lossy().into_owned();
+
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => Zeroizing::new(contents),
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(Error::read(&path_str, err)),
+        };
+
+        let bytes = contents.lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with(COMMENT_PREFIX))
+            .and_then(|line| base64::decode(line).ok())
+            .map(Zeroizing::new)
+            .ok_or_else(|| Error::parse(&path_str, SIGNING_KEY_FILE))?;
+
+        let secret = <[u8; SECRET_KEY_LENGTH]>::try_from(&bytes[..])
+            .map(Zeroizing::new)
+            .map_err(|_| Error::parse(&path_str, SIGNING_KEY_FILE))?;
+
+        Ok(Some(SigningIdentity { key: SigningKey::from_bytes(&secret) }))
+    }
+
+    /// The key others verify our exports with, like "ed25519:...".
+    pub fn public_key(&self) -> String {
+        public_key(&self.key.verifying_key())
+    }
+
+    /// The export with our signature in front.
+    pub fn sign(&self, export: &[u8]) -> Vec<u8> {
+        let signature = self.key.sign(&signed_message(export));
+
+        let mut signed = Vec::with_capacity(MAGIC.len() + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH + export.len());
+        signed.extend_from_slice(MAGIC);
+        signed.extend_from_slice(self.key.verifying_key().as_bytes());
+        signed.extend_from_slice(&signature.to_bytes());
+        signed.extend_from_slice(export);
+        signed
+    }
+}
+
+/// Whether the data is a signed export.
+pub fn is_signed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Checks the signature of a signed export, None when the export isn't signed.
+pub fn verify(data: &[u8]) -> Result<Option<Signed<'_>>, Error> {
+    let rest = match data.strip_prefix(MAGIC) {
+        Some(rest) => rest,
+        None => return Ok(None),
+    };
+
+    if rest.len() < PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH {
+        return Err(Error::Signature(SignatureError::Invalid));
+    }
+
+    let (key, rest) = rest.split_at(PUBLIC_KEY_LENGTH);
+    let (signature, export) = rest.split_at(SIGNATURE_LENGTH);
+
+    let key = VerifyingKey::from_bytes(key.try_into().unwrap()).map_err(|_| Error::Signature(SignatureError::Invalid))?;
+    let signature = Signature::from_bytes(signature.try_into().unwrap());
+
+    key.verify(&signed_message(export), &signature).map_err(|_| Error::Signature(SignatureError::Invalid))?;
+
+    Ok(Some(Signed { signer: public_key(&key), export }))
+}
+
+fn public_key(key: &VerifyingKey) -> String {
+    format!("{}{}", PUBLIC_KEY_PREFIX, base64::encode(key.as_bytes()))
+}
+
+fn signed_message(export: &[u8]) -> Vec<u8> {
+    [DOMAIN, export].concat()
+}
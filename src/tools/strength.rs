@@ -0,0 +1,318 @@
+// Password strength estimation in the spirit of zxcvbn: the password is covered
+// by the cheapest sequence of known patterns and brute-forced gaps, and the
+// product of their guess counts is the estimated number of guesses.
+// Copies of the password made along the way are wiped when dropped.
+
+use zeroize::Zeroizing;
+
+const MIN_MATCH_LEN: usize = 3;
+const MIN_KEYBOARD_LEN: usize = 4;
+const MIN_YEAR: u32 = 1900;
+const MAX_YEAR: u32 = 2039;
+const GUESSES_PER_SECOND: f64 = 1.0e4;
+const SCORE_THRESHOLDS: [f64; 4] = [1.0e3, 1.0e6, 1.0e8, 1.0e10];
+
+const KEYBOARD_ROWS: [&str; 4] = ["1234567890-=", "qwertyuiop[]", "asdfghjkl;'", "zxcvbnm,./"];
+
+const L33T: [(char, char); 9] = [
+    ('4', 'a'), ('@', 'a'), ('3', 'e'), ('1', 'i'), ('!', 'i'), ('0', 'o'), ('5', 's'), ('$', 's'), ('7', 't'),
+];
+
+const COMMON: [&str; 120] = [
+    "password", "123456", "qwerty", "letmein", "admin", "welcome", "monkey", "dragon", "master", "login",
+    "abc123", "football", "iloveyou", "sunshine", "princess", "shadow", "baseball", "superman", "trustno1", "hello",
+    "freedom", "whatever", "michael", "jennifer", "jordan", "hunter", "ranger", "buster", "soccer", "harley",
+    "batman", "andrew", "tigger", "charlie", "robert", "thomas", "hockey", "daniel", "starwars", "welcome1",
+    "george", "computer", "michelle", "jessica", "pepper", "zxcvbn", "ashley", "maggie", "ginger", "summer",
+    "secret", "love", "pass", "test", "guest", "root", "user", "default", "access", "changeme",
+    "qazwsx", "passw0rd", "matrix", "cookie", "orange", "banana", "apple", "cheese", "chocolate", "flower",
+    "mustang", "yankees", "dallas", "austin", "thunder", "taylor", "matthew", "silver", "golden", "killer",
+    "lovely", "angel", "junior", "purple", "family", "monday", "friday", "winter", "spring", "autumn",
+    "november", "december", "january", "october", "august", "hello123", "forever", "nothing", "money", "super",
+    "dragonfly", "internet", "service", "server", "office", "company", "manager", "support", "system", "private",
+    "london", "paris", "berlin", "moscow", "america", "russia", "canada", "london1", "qwertyuiop", "asdfgh",
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Pattern {
+    Dictionary,
+    Sequence,
+    Repeat,
+    Keyboard,
+    Date,
+}
+
+impl Pattern {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pattern::Dictionary => "dictionary word",
+            Pattern::Sequence => "sequence",
+            Pattern::Repeat => "repetition",
+            Pattern::Keyboard => "keyboard pattern",
+            Pattern::Date => "date",
+        }
+    }
+}
+
+pub struct Estimate {
+    pub length: usize,
+    pub guesses: f64,
+    pub score: u8,
+    pub patterns: Vec<Pattern>,
+}
+
+impl Estimate {
+    pub fn crack_seconds(&self) -> f64 {
+        self.guesses / GUESSES_PER_SECOND
+    }
+}
+
+struct Match {
+    start: usize,
+    end: usize,
+    guesses: f64,
+    pattern: Pattern,
+}
+
+pub fn estimate(password: &str) -> Estimate {
+    let chars = Zeroizing::new(password.chars().collect::<Vec<char>>());
+    let matches = find_matches(&chars);
+    let cardinality = cardinality(&chars) as f64;
+
+    // best[i] - the cheapest cover of the first i characters and the match that ends it
+    let mut best: Vec<(f64, Option<usize>)> = vec![(1.0, None)];
+
+    for end in 1..=chars.len() {
+        let mut candidate = (best[end - 1].0 * cardinality, None);
+
+        for (idx, m) in matches.iter().enumerate().filter(|(_, m)| m.end == end) {
+            let guesses = best[m.start].0 * m.guesses;
+            if guesses < candidate.0 {
+                candidate = (guesses, Some(idx));
+            }
+        }
+
+        best.push(candidate);
+    }
+
+    let mut patterns = vec![];
+    let mut pos = chars.len();
+    while pos > 0 {
+        match best[pos].1 {
+            Some(idx) => {
+                if !patterns.contains(&matches[idx].pattern) {
+                    patterns.push(matches[idx].pattern);
+                }
+                pos = matches[idx].start;
+            },
+            None => pos -= 1,
+        }
+    }
+    patterns.reverse();
+
+    let guesses = best[chars.len()].0;
+
+    Estimate {
+        length: chars.len(),
+        guesses,
+        score: SCORE_THRESHOLDS.iter().filter(|t| guesses >= **t).count() as u8,
+        patterns,
+    }
+}
+
+pub fn format_duration(seconds: f64) -> String {
+    const UNITS: [(f64, &str); 6] = [
+        (60.0, "minute"),
+        (3600.0, "hour"),
+        (86400.0, "day"),
+        (2_629_800.0, "month"),
+        (31_557_600.0, "year"),
+        (3_155_760_000.0, "century"),
+    ];
+
+    if seconds < 1.0 {
+        return String::from("less than a second");
+    }
+
+    if seconds >= UNITS[5].0 {
+        return String::from("centuries");
+    }
+
+    let (unit, name) = UNITS.iter().rev()
+        .find(|(unit, _)| seconds >= *unit)
+        .copied()
+        .unwrap_or((1.0, "second"));
+
+    let count = (seconds / unit).round() as u64;
+    format!("{} {}{}", count, name, if count == 1 { "" } else { "s" })
+}
+
+pub fn edit_distance(first: &str, second: &str) -> usize {
+    let second = Zeroizing::new(second.chars().collect::<Vec<char>>());
+    let mut row = (0..=second.len()).collect::<Vec<usize>>();
+
+    for (i, a) in first.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, b) in second.iter().enumerate() {
+            let substitution = diagonal + if a == *b { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+
+    row[second.len()]
+}
+
+fn cardinality(chars: &[char]) -> u32 {
+    let mut card = 0;
+    if chars.iter().any(|c| c.is_ascii_lowercase()) { card += 26; }
+    if chars.iter().any(|c| c.is_ascii_uppercase()) { card += 26; }
+    if chars.iter().any(|c| c.is_ascii_digit()) { card += 10; }
+    if chars.iter().any(|c| c.is_ascii_punctuation() || *c == ' ') { card += 33; }
+    if chars.iter().any(|c| !c.is_ascii()) { card += 100; }
+    card.max(10)
+}
+
+fn find_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = vec![];
+    dictionary_matches(chars, &mut matches);
+    sequence_matches(chars, &mut matches);
+    repeat_matches(chars, &mut matches);
+    keyboard_matches(chars, &mut matches);
+    date_matches(chars, &mut matches);
+    matches
+}
+
+fn unleet(c: char) -> char {
+    L33T.iter().find(|(from, _)| *from == c).map(|(_, to)| *to).unwrap_or(c)
+}
+
+fn dictionary_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let lower = Zeroizing::new(chars.iter().map(|c| c.to_ascii_lowercase()).collect::<Vec<char>>());
+    let unleeted = Zeroizing::new(lower.iter().map(|c| unleet(*c)).collect::<Vec<char>>());
+
+    for (rank, word) in COMMON.iter().enumerate() {
+        let word = word.chars().collect::<Vec<char>>();
+        if word.len() > chars.len() { continue; }
+
+        for start in 0..=chars.len() - word.len() {
+            let end = start + word.len();
+            let plain = lower[start..end] == word[..];
+
+            if !plain && unleeted[start..end] != word[..] { continue; }
+
+            let uppercase = chars[start..end].iter().filter(|c| c.is_ascii_uppercase()).count();
+            let mut guesses = (rank + 1) as f64;
+            if uppercase > 0 { guesses *= if uppercase == 1 && chars[start].is_ascii_uppercase() { 2.0 } else { 2.0 * uppercase as f64 }; }
+            if !plain { guesses *= 2.0; }
+
+            matches.push(Match { start, end, guesses, pattern: Pattern::Dictionary });
+        }
+    }
+}
+
+fn sequence_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let mut start = 0;
+
+    while start + 1 < chars.len() {
+        let delta = chars[start + 1] as i32 - chars[start] as i32;
+        let mut end = start + 1;
+
+        if delta == 1 || delta == -1 {
+            while end < chars.len() && chars[end] as i32 - chars[end - 1] as i32 == delta {
+                end += 1;
+            }
+        }
+
+        if end - start >= MIN_MATCH_LEN {
+            let base = match chars[start] {
+                'a' | 'A' | 'z' | 'Z' | '0' | '1' | '9' => 4.0,
+                c if c.is_ascii_digit() => 10.0,
+                _ => 26.0,
+            };
+            let direction = if delta < 0 { 2.0 } else { 1.0 };
+            let guesses = base * (end - start) as f64 * direction;
+
+            matches.push(Match { start, end, guesses, pattern: Pattern::Sequence });
+            start = end;
+        } else {
+            start += 1;
+        }
+    }
+}
+
+fn repeat_matches(chars: &[char], matches: &mut Vec<Match>) {
+    for start in 0..chars.len() {
+        for block in 1..=(chars.len() - start) / 2 {
+            let mut end = start + block;
+            while end + block <= chars.len() && chars[end..end + block] == chars[start..start + block] {
+                end += block;
+            }
+
+            let count = (end - start) / block;
+            if count < 2 || end - start < MIN_MATCH_LEN { continue; }
+
+            let block_guesses = (cardinality(&chars[start..start + block]) as f64).powi(block as i32);
+            let guesses = block_guesses * count as f64;
+
+            matches.push(Match { start, end, guesses, pattern: Pattern::Repeat });
+        }
+    }
+}
+
+fn keyboard_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let lower = Zeroizing::new(chars.iter().map(|c| c.to_ascii_lowercase()).collect::<String>());
+
+    for row in KEYBOARD_ROWS.iter() {
+        let reversed = row.chars().rev().collect::<String>();
+
+        for line in [row.to_string(), reversed].iter() {
+            for len in MIN_KEYBOARD_LEN..=line.len() {
+                for window_start in 0..=line.len() - len {
+                    let window = &line[window_start..window_start + len];
+
+                    for (start, _) in lower.match_indices(window) {
+                        let start = lower[..start].chars().count();
+                        let guesses = (KEYBOARD_ROWS.len() * line.len() * 2 * len) as f64;
+                        matches.push(Match { start, end: start + len, guesses, pattern: Pattern::Keyboard });
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn date_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let digits = |start: usize, len: usize| -> Option<String> {
+        let slice = chars.get(start..start + len)?;
+        match slice.iter().all(|c| c.is_ascii_digit()) {
+            true => Some(slice.iter().collect()),
+            false => None,
+        }
+    };
+
+    let year_range = (MAX_YEAR - MIN_YEAR + 1) as f64;
+
+    for start in 0..chars.len() {
+        if let Some(year) = digits(start, 4) {
+            if (MIN_YEAR..=MAX_YEAR).contains(&year.parse().unwrap_or(0)) {
+                matches.push(Match { start, end: start + 4, guesses: year_range, pattern: Pattern::Date });
+            }
+        }
+
+        for len in [6, 8].iter() {
+            if let Some(date) = digits(start, *len) {
+                let first = date[0..2].parse::<u32>().unwrap_or(0);
+                let second = date[2..4].parse::<u32>().unwrap_or(0);
+                let (day, month) = (first.max(second), first.min(second));
+
+                if (1..=31).contains(&day) && (1..=12).contains(&month) {
+                    let guesses = 31.0 * 12.0 * if *len == 8 { year_range } else { 100.0 };
+                    matches.push(Match { start, end: start + len, guesses, pattern: Pattern::Date });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+use crate::tools::merger::Base;
+
+const SYNC_SUFFIX: &str = ".sync";
+const SYNC_STATE: &str = "sync state";
+
+/// Where the store keeps what each export file looked like when it was last imported or
+/// written, one file per export in "<store>.sync" named after a hash of its path.
+/// The first line is the salt, then one "key\0hash" line per entry, all hex encoded.
+pub fn base_path(store_path: &str, source: &str) -> PathBuf {
+    let source = fs::canonicalize(source).unwrap_or_else(|_| PathBuf::from(source));
+    let id = Sha256::digest(source.to_string_lossy().as_bytes());
+
+    PathBuf::from(format!("{}{}", store_path, SYNC_SUFFIX)).join(to_hex(&id[..16]))
+}
+
+/// The base of the source, None when it was never synced.
+pub fn load(store_path: &str, source: &str) -> Result<Option<Base>, Error> {
+    let path = base_path(store_path, source);
+    let path_str = path.to_string_lossy().into_owned();
+
+    let s = match fs::read_to_string(&path) {
+        Ok(s) => s,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(Error::read(&path_str, err)),
+    };
+
+    parse(&s).map(Some).ok_or_else(|| Error::parse(&path_str, SYNC_STATE))
+}
+
+pub fn save(store_path: &str, source: &str, base: &Base) -> Result<(), Error> {
+    let path = base_path(store_path, source);
+    let path_str = path.to_string_lossy().into_owned();
+
+    if let Some(dir) = Path::new(&path).parent() {
+        fs::create_dir_all(dir).map_err(|err| Error::write(&path_str, err))?;
+    }
+
+    let mut lines = vec![to_hex(base.salt())];
+    lines.extend(base.entries().iter().map(|(key, hash)| format!("{}\u{0}{}", key, to_hex(hash))));
+
+    fs::write(&path, lines.join("\n") + "\n").map_err(|err| Error::write(&path_str, err))
+}
+
+/// Forgets the source, its next import is merged as if it was never synced.
+pub fn forget(store_path: &str, source: &str) -> Result<(), Error> {
+    let path = base_path(store_path, source);
+
+    match fs::remove_file(&path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(Error::write(&path.to_string_lossy(), err)),
+        _ => Ok(()),
+    }
+}
+
+fn parse(s: &str) -> Option<Base> {
+    let mut lines = s.split('\n').filter(|line| !line.is_empty());
+    let salt = from_hex(lines.next()?)?;
+
+    let entries = lines
+        .map(|line| line.split_once('\u{0}').and_then(|(key, hash)| Some((String::from(key), from_hex(hash)?))))
+        .collect::<Option<HashMap<String, Vec<u8>>>>()?;
+
+    Some(Base::from_parts(salt, entries))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// An odd length fails, the last byte would run past the end.
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    (0..s.len()).step_by(2)
+        .map(|i| s.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}